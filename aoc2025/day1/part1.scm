@@ -1,7 +1,7 @@
 (begin
     (define pos 50)
     (define inputs (split-string (read-file "aoc2025/day1/input")))
-    (define inputs (map (lambda (s) (list (substring s 0 1) (string->int (substring s 1 -1)))) inputs))
+    (define inputs (map (lambda (s) (list (substring s 0 1) (string->number (substring s 1 -1)))) inputs))
 
     (define (dial direction amount)
         (if (= direction "R")
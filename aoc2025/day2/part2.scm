@@ -3,7 +3,7 @@
     (define inputs (split-string-with (read-file "aoc2025/day2/input") ","))
     (define (parse-input s)
         (define strings (split-string-with s "-"))
-        (map string->int strings)
+        (map string->number strings)
     )
     (define inputs (map parse-input inputs))
 
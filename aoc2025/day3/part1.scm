@@ -8,13 +8,13 @@
             ((i 0 (+ i 1)))
             ((>= i (- len 1)))
 
-            (define v1 (* 10 (string->int (string-ref line i))))
+            (define v1 (* 10 (digit-value (string-ref line i))))
 
             (do
                 ((j (+ i 1) (+ j 1)))
                 ((>= j len))
 
-                (define v2 (string->int (string-ref line j)))
+                (define v2 (digit-value (string-ref line j)))
                 (define candidate (+ v1 v2))
                 (if
                     (> candidate max)
@@ -1,8 +1,33 @@
-use std::rc::Rc;
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    rc::Rc,
+};
 
-use crate::{eval::RuntimeError, value::Value};
+use crate::{
+    eval::{Arity, RuntimeError},
+    parser,
+    scope::Scope,
+    source::Source,
+    tokenizer::{self, Number, float_to_string},
+    value::{Sharing, Value},
+};
 use itertools::Itertools;
 
+/// Destructures arguments of a function that takes exactly `N` of them
+fn exactly<T, const N: usize>(params: Vec<T>) -> Result<[T; N], RuntimeError> {
+    let passed = params.len();
+    params
+        .try_into()
+        .map_err(|_| arity_error(Arity::Exactly(N), passed))
+}
+
+fn arity_error(expected: Arity, passed: usize) -> RuntimeError {
+    RuntimeError::WrongNumberOfAgumentsPassed { expected, passed }
+}
+
 fn values_to_ints(params: &[Value]) -> Result<Vec<i64>, RuntimeError> {
     params
         .iter()
@@ -33,12 +58,12 @@ fn values_to_strings(params: &[Value]) -> Result<Vec<Rc<String>>, RuntimeError>
         .collect()
 }
 
-fn values_to_bools(params: &[Value]) -> Result<Vec<bool>, RuntimeError> {
+fn values_to_chars(params: &[Value]) -> Result<Vec<char>, RuntimeError> {
     params
         .iter()
         .map(|param| match param {
-            Value::Bool(b) => Ok(*b),
-            _ => Err(RuntimeError::BooleanExpected(param.clone())),
+            Value::Char(c) => Ok(*c),
+            _ => Err(RuntimeError::CharExpected(param.clone())),
         })
         .collect()
 }
@@ -52,6 +77,35 @@ fn values_to_vecs(params: impl Iterator<Item = Value>) -> Result<Vec<Vec<Value>>
         .collect::<Result<Vec<_>, RuntimeError>>()
 }
 
+fn values_to_vectors(params: &[Value]) -> Result<Vec<Rc<RefCell<Vec<Value>>>>, RuntimeError> {
+    params
+        .iter()
+        .map(|param| match param {
+            Value::Vector(v) => Ok(v.clone()),
+            _ => Err(RuntimeError::VectorExpected(param.clone())),
+        })
+        .collect()
+}
+
+type HashTable = Rc<RefCell<HashMap<Value, Value>>>;
+
+fn value_to_hash_table(param: &Value) -> Result<HashTable, RuntimeError> {
+    match param {
+        Value::HashTable(t) => Ok(t.clone()),
+        _ => Err(RuntimeError::HashTableExpected(param.clone())),
+    }
+}
+
+fn values_to_sets(params: &[Value]) -> Result<Vec<Rc<RefCell<HashSet<Value>>>>, RuntimeError> {
+    params
+        .iter()
+        .map(|param| match param {
+            Value::Set(s) => Ok(s.clone()),
+            _ => Err(RuntimeError::SetExpected(param.clone())),
+        })
+        .collect()
+}
+
 pub fn op_add(params: Vec<Value>) -> Result<Value, RuntimeError> {
     match params.first() {
         None => Ok(Value::Int(0)),
@@ -63,7 +117,7 @@ pub fn op_add(params: Vec<Value>) -> Result<Value, RuntimeError> {
 
 pub fn op_sub(params: Vec<Value>) -> Result<Value, RuntimeError> {
     match params.first() {
-        None => Err(RuntimeError::WrongNumberOfAgumentsPassed),
+        None => Err(arity_error(Arity::AtLeast(1), 0)),
         Some(Value::Int(_)) => {
             let ops = values_to_ints(&params)?;
             if ops.len() == 1 {
@@ -99,7 +153,7 @@ pub fn op_mul(params: Vec<Value>) -> Result<Value, RuntimeError> {
 
 pub fn op_div(params: Vec<Value>) -> Result<Value, RuntimeError> {
     match params.first() {
-        None => Err(RuntimeError::WrongNumberOfAgumentsPassed),
+        None => Err(arity_error(Arity::AtLeast(1), 0)),
         Some(Value::Int(_)) => {
             let ops = values_to_ints(&params)?;
             if ops.len() == 1 {
@@ -141,9 +195,7 @@ pub fn op_div(params: Vec<Value>) -> Result<Value, RuntimeError> {
 }
 
 pub fn modulo(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let [a, b] = values_to_ints(&params)?
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
+    let [a, b] = exactly(values_to_ints(&params)?)?;
     if b == 0 {
         Err(RuntimeError::DivideByZero)
     } else {
@@ -154,26 +206,209 @@ pub fn modulo(params: Vec<Value>) -> Result<Value, RuntimeError> {
 pub fn op_eq(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.is_empty() {
         Ok(Value::Bool(true))
-    } else if let Ok(ops) = values_to_ints(&params) {
-        let first = ops[0];
-        Ok(Value::Bool(ops.into_iter().all(|v| v == first)))
+    } else if params
+        .iter()
+        .all(|param| matches!(param, Value::Int(_) | Value::Float(_)))
+    {
+        let ans = params
+            .iter()
+            .tuple_windows()
+            .all(|(a, b)| number_partial_cmp(a, b).is_some_and(Ordering::is_eq));
+        Ok(Value::Bool(ans))
     } else if let Ok(ops) = values_to_strings(&params) {
         let first = &ops[0];
         Ok(Value::Bool(ops.iter().all(|v| v == first)))
     } else {
-        Err(RuntimeError::NumberExpected(params[0].clone()))
+        let param = params
+            .iter()
+            .find(|param| !matches!(param, Value::Int(_) | Value::Float(_)))
+            .unwrap_or(&params[0]);
+        Err(RuntimeError::NumberExpected(param.clone()))
+    }
+}
+
+pub fn is_eqv(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [a, b] = exactly(params)?;
+    Ok(Value::Bool(a.eqv(&b)))
+}
+
+pub fn is_equal(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [a, b] = exactly(params)?;
+    Ok(Value::Bool(a == b))
+}
+
+fn number_partial_cmp(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        _ => None,
     }
 }
 
 pub fn pairwise_compare(
     params: &[Value],
-    cmp: fn((i64, i64)) -> bool,
+    cmp: fn(Ordering) -> bool,
+) -> Result<Value, RuntimeError> {
+    if let Some(param) = params
+        .iter()
+        .find(|param| !matches!(param, Value::Int(_) | Value::Float(_)))
+    {
+        return Err(RuntimeError::NumberExpected(param.clone()));
+    }
+    // comparisons involving NaN are always false
+    let ans = params
+        .iter()
+        .tuple_windows()
+        .all(|(a, b)| number_partial_cmp(a, b).is_some_and(cmp));
+    Ok(Value::Bool(ans))
+}
+
+pub fn string_compare(
+    params: &[Value],
+    cmp: fn(Ordering) -> bool,
+    case_insensitive: bool,
 ) -> Result<Value, RuntimeError> {
-    let ops = values_to_ints(params)?;
-    let ans = ops.into_iter().tuple_windows().all(cmp);
+    let strings = values_to_strings(params)?;
+    let ans = if case_insensitive {
+        strings
+            .iter()
+            .map(|s| s.to_lowercase())
+            .tuple_windows()
+            .all(|(a, b)| cmp(a.cmp(&b)))
+    } else {
+        strings.iter().tuple_windows().all(|(a, b)| cmp(a.cmp(b)))
+    };
     Ok(Value::Bool(ans))
 }
 
+pub fn char_compare(
+    params: &[Value],
+    cmp: fn(Ordering) -> bool,
+    case_insensitive: bool,
+) -> Result<Value, RuntimeError> {
+    let chars = values_to_chars(params)?;
+    let ans = if case_insensitive {
+        chars
+            .iter()
+            .map(|c| downcase(*c))
+            .tuple_windows()
+            .all(|(a, b)| cmp(a.cmp(&b)))
+    } else {
+        chars.iter().tuple_windows().all(|(a, b)| cmp(a.cmp(b)))
+    };
+    Ok(Value::Bool(ans))
+}
+
+/// Total ordering of values used by `compare`. Numbers of different exactness are compared
+/// by value, lists are compared lexicographically. Values of different kinds are incomparable.
+pub fn compare_values(a: &Value, b: &Value) -> Result<Ordering, RuntimeError> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok(a.cmp(b)),
+        (Value::Int(a), Value::Float(b)) => Ok((*a as f64).total_cmp(b)),
+        (Value::Float(a), Value::Int(b)) => Ok(a.total_cmp(&(*b as f64))),
+        (Value::Float(a), Value::Float(b)) => Ok(a.total_cmp(b)),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        (Value::Char(a), Value::Char(b)) => Ok(a.cmp(b)),
+        (Value::Symbol(a), Value::Symbol(b)) => Ok(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Ok(a.cmp(b)),
+        (Value::List(a), Value::List(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                let ord = compare_values(a, b)?;
+                if ord.is_ne() {
+                    return Ok(ord);
+                }
+            }
+            Ok(a.len().cmp(&b.len()))
+        }
+        (a, b) => Err(RuntimeError::IncomparableValues(a.clone(), b.clone())),
+    }
+}
+
+pub fn compare(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [a, b] = exactly(params)?;
+    let ans = match compare_values(&a, &b)? {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    };
+    Ok(Value::Int(ans))
+}
+
+pub fn bitwise_and(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let ops = values_to_ints(&params)?;
+    Ok(Value::Int(ops.into_iter().fold(-1, |acc, op| acc & op)))
+}
+
+pub fn bitwise_or(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let ops = values_to_ints(&params)?;
+    Ok(Value::Int(ops.into_iter().fold(0, |acc, op| acc | op)))
+}
+
+pub fn bitwise_xor(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let ops = values_to_ints(&params)?;
+    Ok(Value::Int(ops.into_iter().fold(0, |acc, op| acc ^ op)))
+}
+
+pub fn bitwise_not(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = exactly(values_to_ints(&params)?)?;
+    Ok(Value::Int(!n))
+}
+
+pub fn arithmetic_shift(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n, shift] = exactly(values_to_ints(&params)?)?;
+    if shift >= 0 {
+        // left shift must not lose any significant bits
+        let shifted = n.checked_shl(shift.min(64) as u32).unwrap_or(0);
+        if shifted >> shift.min(63) != n || (shift >= 64 && n != 0) {
+            return Err(RuntimeError::IntegerOverflow);
+        }
+        Ok(Value::Int(shifted))
+    } else {
+        Ok(Value::Int(n >> shift.unsigned_abs().min(63)))
+    }
+}
+
+pub fn bit_count(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = exactly(values_to_ints(&params)?)?;
+    // negative numbers have infinitely many leading ones, so their zeros are counted instead
+    let count = if n >= 0 {
+        n.count_ones()
+    } else {
+        n.count_zeros()
+    };
+    Ok(Value::Int(count as i64))
+}
+
+pub fn bit_set(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [index, n] = exactly(values_to_ints(&params)?)?;
+    if index < 0 {
+        return Err(RuntimeError::IndexOutOfRange(index));
+    }
+    Ok(Value::Bool((n >> index.min(63)) & 1 == 1))
+}
+
+pub fn first_set_bit(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = exactly(values_to_ints(&params)?)?;
+    let index = if n == 0 {
+        -1
+    } else {
+        n.trailing_zeros() as i64
+    };
+    Ok(Value::Int(index))
+}
+
+pub fn integer_length(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = exactly(values_to_ints(&params)?)?;
+    let length = if n >= 0 {
+        n.leading_zeros()
+    } else {
+        n.leading_ones()
+    };
+    Ok(Value::Int(64 - length as i64))
+}
+
 pub fn list(params: Vec<Value>) -> Result<Value, RuntimeError> {
     Ok(Value::List(params))
 }
@@ -186,9 +421,9 @@ pub fn iota(params: Vec<Value>) -> Result<Value, RuntimeError> {
         [count, start, step] => {
             Box::new((*start..(*count * *step) + *start).step_by(*step as usize))
         }
-        _ => return Err(RuntimeError::WrongNumberOfAgumentsPassed),
+        _ => return Err(arity_error(Arity::Between(1, 3), params.len())),
     };
-    Ok(Value::List(ans.map(|v| Value::Int(v)).collect()))
+    Ok(Value::List(ans.map(Value::Int).collect()))
 }
 
 pub fn append(params: Vec<Value>) -> Result<Value, RuntimeError> {
@@ -203,9 +438,298 @@ pub fn append(params: Vec<Value>) -> Result<Value, RuntimeError> {
     Ok(Value::List(vals?))
 }
 
+pub fn vector(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::new_vector(params))
+}
+
+pub fn make_vector(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (len, fill) = match params.as_slice() {
+        [Value::Int(len)] => (*len, Value::Unspecified),
+        [Value::Int(len), fill] => (*len, fill.clone()),
+        [len] | [len, _] => return Err(RuntimeError::NumberExpected(len.clone())),
+        _ => return Err(arity_error(Arity::Between(1, 2), params.len())),
+    };
+    let len = usize::try_from(len).map_err(|_| RuntimeError::IndexOutOfRange(len))?;
+    Ok(Value::new_vector(vec![fill; len]))
+}
+
+fn vector_index(index: &Value, len: usize) -> Result<usize, RuntimeError> {
+    match index {
+        Value::Int(i) => usize::try_from(*i)
+            .ok()
+            .filter(|i| *i < len)
+            .ok_or(RuntimeError::IndexOutOfRange(*i)),
+        v => Err(RuntimeError::NumberExpected(v.clone())),
+    }
+}
+
+/// Optional `start` and `end` parameters of vector procedures, defaulting to the whole vector
+fn vector_range(params: &[Value], len: usize) -> Result<Range<usize>, RuntimeError> {
+    let bounds = values_to_ints(params)?;
+    let bound = |i: i64| {
+        usize::try_from(i)
+            .ok()
+            .filter(|i| *i <= len)
+            .ok_or(RuntimeError::IndexOutOfRange(i))
+    };
+    let range = match bounds.as_slice() {
+        [] => 0..len,
+        [start] => bound(*start)?..len,
+        [start, end] => bound(*start)?..bound(*end)?,
+        // callers check the number of arguments including the optional bounds
+        _ => return Err(arity_error(Arity::Between(0, 2), bounds.len())),
+    };
+    if range.start > range.end {
+        return Err(RuntimeError::IndexOutOfRange(range.start as i64));
+    }
+    Ok(range)
+}
+
+pub fn vector_ref(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [vector, index] = exactly(params)?;
+    let vector = values_to_vectors(&[vector])?.remove(0);
+    let vector = vector.borrow();
+    let index = vector_index(&index, vector.len())?;
+    Ok(vector[index].clone())
+}
+
+pub fn vector_set(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [vector, index, value] = exactly(params)?;
+    let vector = values_to_vectors(&[vector])?.remove(0);
+    let mut vector = vector.borrow_mut();
+    let index = vector_index(&index, vector.len())?;
+    vector[index] = value;
+    Ok(Value::Unspecified)
+}
+
+pub fn vector_length(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [vector] = exactly(values_to_vectors(&params)?)?;
+    Ok(Value::Int(vector.borrow().len() as i64))
+}
+
+pub fn vector_fill(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    if !(2..=4).contains(&params.len()) {
+        return Err(arity_error(Arity::Between(2, 4), params.len()));
+    }
+    let vector = values_to_vectors(&params[..1])?.remove(0);
+    let mut vector = vector.borrow_mut();
+    let range = vector_range(&params[2..], vector.len())?;
+    vector[range].fill(params[1].clone());
+    Ok(Value::Unspecified)
+}
+
+pub fn vector_copy(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    if !(1..=3).contains(&params.len()) {
+        return Err(arity_error(Arity::Between(1, 3), params.len()));
+    }
+    let vector = values_to_vectors(&params[..1])?.remove(0);
+    let vector = vector.borrow();
+    let range = vector_range(&params[1..], vector.len())?;
+    Ok(Value::new_vector(vector[range].to_vec()))
+}
+
+fn vector_zip_call(params: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    if params.len() < 2 {
+        return Err(arity_error(Arity::AtLeast(2), params.len()));
+    }
+
+    let func = &params[0];
+    // take snapshots so that the procedure may safely mutate the vectors
+    let vectors: Vec<Vec<Value>> = values_to_vectors(&params[1..])?
+        .iter()
+        .map(|v| v.borrow().clone())
+        .collect();
+
+    zip_vecs(&vectors)
+        .map(|zipped_params| crate::special_forms::func_call(func, zipped_params))
+        .collect()
+}
+
+pub fn vector_map(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::new_vector(vector_zip_call(params)?))
+}
+
+pub fn vector_for_each(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    vector_zip_call(params)?;
+    Ok(Value::Unspecified)
+}
+
+pub fn vector_to_list(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    if !(1..=3).contains(&params.len()) {
+        return Err(arity_error(Arity::Between(1, 3), params.len()));
+    }
+    let vector = values_to_vectors(&params[..1])?.remove(0);
+    let vector = vector.borrow();
+    let range = vector_range(&params[1..], vector.len())?;
+    Ok(Value::List(vector[range].to_vec()))
+}
+
+pub fn list_to_vector(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list] = exactly(values_to_vecs(params.into_iter())?)?;
+    Ok(Value::new_vector(list))
+}
+
+pub fn make_hash_table(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    if !params.is_empty() {
+        return Err(arity_error(Arity::Exactly(0), params.len()));
+    }
+    Ok(Value::new_hash_table())
+}
+
+pub fn hash_table_ref(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (table, key, fail) = match params.as_slice() {
+        [table, key] => (table, key, None),
+        [table, key, fail] => (table, key, Some(fail)),
+        _ => return Err(arity_error(Arity::Between(2, 3), params.len())),
+    };
+    let value = value_to_hash_table(table)?.borrow().get(key).cloned();
+    match (value, fail) {
+        (Some(value), _) => Ok(value),
+        // the table is not borrowed anymore so the thunk may modify it
+        (None, Some(fail)) => crate::special_forms::func_call(fail, vec![]),
+        (None, None) => Err(RuntimeError::KeyNotFound(key.clone())),
+    }
+}
+
+pub fn hash_table_ref_default(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [table, key, default] = exactly(params)?;
+    let value = value_to_hash_table(&table)?.borrow().get(&key).cloned();
+    Ok(value.unwrap_or(default))
+}
+
+pub fn hash_table_set(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [table, key, value] = exactly(params)?;
+    value_to_hash_table(&table)?.borrow_mut().insert(key, value);
+    Ok(Value::Unspecified)
+}
+
+pub fn hash_table_update_default(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [table, key, func, default] = exactly(params)?;
+    let table = value_to_hash_table(&table)?;
+    let value = table.borrow().get(&key).cloned().unwrap_or(default);
+    let value = crate::special_forms::func_call(&func, vec![value])?;
+    table.borrow_mut().insert(key, value);
+    Ok(Value::Unspecified)
+}
+
+pub fn hash_table_delete(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [table, key] = exactly(params)?;
+    value_to_hash_table(&table)?.borrow_mut().remove(&key);
+    Ok(Value::Unspecified)
+}
+
+pub fn hash_table_contains(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [table, key] = exactly(params)?;
+    let contains = value_to_hash_table(&table)?.borrow().contains_key(&key);
+    Ok(Value::Bool(contains))
+}
+
+fn single_hash_table(params: Vec<Value>) -> Result<HashTable, RuntimeError> {
+    let [table] = exactly(params)?;
+    value_to_hash_table(&table)
+}
+
+pub fn hash_table_count(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let table = single_hash_table(params)?;
+    Ok(Value::Int(table.borrow().len() as i64))
+}
+
+pub fn hash_table_keys(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let table = single_hash_table(params)?;
+    Ok(Value::List(table.borrow().keys().cloned().collect()))
+}
+
+pub fn hash_table_values(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let table = single_hash_table(params)?;
+    Ok(Value::List(table.borrow().values().cloned().collect()))
+}
+
+pub fn hash_table_to_alist(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let table = single_hash_table(params)?;
+    let entries = table
+        .borrow()
+        .iter()
+        .map(|(key, value)| Value::List(vec![key.clone(), value.clone()]))
+        .collect();
+    Ok(Value::List(entries))
+}
+
+pub fn hash_table_walk(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [table, func] = exactly(params)?;
+    // iterate over a snapshot so that the procedure may modify the table
+    let entries: Vec<_> = value_to_hash_table(&table)?
+        .borrow()
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    for (key, value) in entries {
+        crate::special_forms::func_call(&func, vec![key, value])?;
+    }
+    Ok(Value::Unspecified)
+}
+
+pub fn set(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::new_set(params.into_iter().collect()))
+}
+
+pub fn set_add(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    if params.is_empty() {
+        return Err(arity_error(Arity::AtLeast(1), 0));
+    }
+    let mut params = params.into_iter();
+    let set = values_to_sets(&[params.next().unwrap()])?.remove(0);
+    set.borrow_mut().extend(params);
+    Ok(Value::Unspecified)
+}
+
+pub fn set_contains(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set, item] = exactly(params)?;
+    let set = values_to_sets(&[set])?.remove(0);
+    let contains = set.borrow().contains(&item);
+    Ok(Value::Bool(contains))
+}
+
+/// Folds all sets passed as parameters into a new set, first one is taken as initial value
+#[allow(clippy::mutable_key_type)] // see `Hash for Value`
+fn fold_sets(
+    params: &[Value],
+    op: fn(&HashSet<Value>, &HashSet<Value>) -> HashSet<Value>,
+) -> Result<Value, RuntimeError> {
+    let sets = values_to_sets(params)?;
+    let (first, rest) = sets
+        .split_first()
+        .ok_or(arity_error(Arity::AtLeast(1), 0))?;
+    let ans = rest
+        .iter()
+        .fold(first.borrow().clone(), |acc, set| op(&acc, &set.borrow()));
+    Ok(Value::new_set(ans))
+}
+
+pub fn set_union(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    fold_sets(&params, |a, b| a.union(b).cloned().collect())
+}
+
+pub fn set_intersection(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    fold_sets(&params, |a, b| a.intersection(b).cloned().collect())
+}
+
+pub fn set_difference(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    fold_sets(&params, |a, b| a.difference(b).cloned().collect())
+}
+
+pub fn set_to_list(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set] = exactly(values_to_sets(&params)?)?;
+    Ok(Value::List(set.borrow().iter().cloned().collect()))
+}
+
+pub fn set_size(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set] = exactly(values_to_sets(&params)?)?;
+    Ok(Value::Int(set.borrow().len() as i64))
+}
+
 pub fn string_concat(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.is_empty() {
-        Err(RuntimeError::WrongNumberOfAgumentsPassed)
+        Err(arity_error(Arity::AtLeast(1), 0))
     } else {
         let args = values_to_strings(&params)?;
         let mut ans = String::new();
@@ -220,7 +744,30 @@ pub fn display(params: Vec<Value>) -> Result<Value, RuntimeError> {
     for param in params {
         print!("{}", param);
     }
-    Ok(Value::Nil)
+    Ok(Value::Unspecified)
+}
+
+/// Prints the values so that `read` turns them back into equal data, circular structure is
+/// written with datum labels
+pub fn write(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    write_values(params, Sharing::Cycles)
+}
+
+/// Like `write`, but labels all structure that appears more than once
+pub fn write_shared(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    write_values(params, Sharing::Always)
+}
+
+/// Like `write` without datum labels, does not return on circular structure
+pub fn write_simple(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    write_values(params, Sharing::Never)
+}
+
+fn write_values(params: Vec<Value>, sharing: Sharing) -> Result<Value, RuntimeError> {
+    for param in params {
+        print!("{}", param.written(sharing));
+    }
+    Ok(Value::Unspecified)
 }
 
 fn zip_vecs<T: Clone>(v: &[Vec<T>]) -> impl Iterator<Item = Vec<T>> {
@@ -230,7 +777,7 @@ fn zip_vecs<T: Clone>(v: &[Vec<T>]) -> impl Iterator<Item = Vec<T>> {
 
 pub fn map(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.len() < 2 {
-        return Err(RuntimeError::WrongNumberOfAgumentsPassed);
+        return Err(arity_error(Arity::AtLeast(2), params.len()));
     }
 
     let mut params = params.into_iter();
@@ -245,33 +792,168 @@ pub fn map(params: Vec<Value>) -> Result<Value, RuntimeError> {
 }
 
 pub fn apply(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let [func, params] = params
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
+    let [func, params] = exactly(params)?;
     match params {
         Value::List(params) => crate::special_forms::func_call(&func, params),
         v => Err(RuntimeError::ListExpected(v.clone())),
     }
 }
 
+pub fn is_procedure(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    Ok(Value::Bool(matches!(
+        param,
+        Value::BuiltIn(_) | Value::Procedure(_) | Value::RecordProcedure(_)
+    )))
+}
+
+/// Exact arity is an integer, others are lists of minimum and maximum with `#f` for no maximum
+fn arity_to_value(arity: Arity) -> Value {
+    match arity {
+        Arity::Exactly(n) => Value::Int(n as i64),
+        Arity::AtLeast(min) => Value::List(vec![Value::Int(min as i64), Value::Bool(false)]),
+        Arity::Between(min, max) => {
+            Value::List(vec![Value::Int(min as i64), Value::Int(max as i64)])
+        }
+    }
+}
+
+pub fn procedure_arity(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    match param {
+        Value::Procedure(proc) => Ok(arity_to_value(proc.arity())),
+        Value::RecordProcedure(proc) => Ok(arity_to_value(proc.arity())),
+        // built-ins check their arguments themselves, their arity is not recorded
+        Value::BuiltIn(_) => Ok(Value::Bool(false)),
+        v => Err(RuntimeError::NotProcedure(v)),
+    }
+}
+
+pub fn procedure_name(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    let name = match &param {
+        Value::Procedure(proc) => proc.name(),
+        Value::BuiltIn(builtin) => Some(builtin.name),
+        Value::RecordProcedure(_) => None,
+        _ => return Err(RuntimeError::NotProcedure(param)),
+    };
+    Ok(name.map_or(Value::Bool(false), |name| {
+        Value::Symbol(Rc::new(name.to_string()))
+    }))
+}
+
+pub fn procedure_source(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    match param {
+        Value::Procedure(proc) => Ok(Value::String(Rc::new(proc.source()))),
+        Value::BuiltIn(_) | Value::RecordProcedure(_) => Ok(Value::Bool(false)),
+        v => Err(RuntimeError::NotProcedure(v)),
+    }
+}
+
+/// Association list of bindings captured by a procedure, global bindings are not included
+pub fn procedure_environment(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    let bindings = match param {
+        Value::Procedure(proc) => proc.captured_bindings(),
+        Value::BuiltIn(_) | Value::RecordProcedure(_) => vec![],
+        v => return Err(RuntimeError::NotProcedure(v)),
+    };
+    let bindings = bindings
+        .into_iter()
+        .map(|(name, value)| Value::List(vec![Value::Symbol(Rc::new(name)), value]))
+        .collect();
+    Ok(Value::List(bindings))
+}
+
+fn value_to_environment(param: &Value) -> Result<Rc<Scope>, RuntimeError> {
+    match param {
+        Value::Environment(e) => Ok(e.clone()),
+        _ => Err(RuntimeError::EnvironmentExpected(param.clone())),
+    }
+}
+
+fn value_to_symbol(param: &Value) -> Result<Rc<String>, RuntimeError> {
+    match param {
+        Value::Symbol(s) => Ok(s.clone()),
+        _ => Err(RuntimeError::SymbolExpected(param.clone())),
+    }
+}
+
+pub fn environment_bound_names(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [environment] = exactly(params)?;
+    let names = Scope::bound_names(&value_to_environment(&environment)?)
+        .into_iter()
+        .map(|name| Value::Symbol(Rc::new(name)))
+        .collect();
+    Ok(Value::List(names))
+}
+
+pub fn environment_ref(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [environment, symbol] = exactly(params)?;
+    let environment = value_to_environment(&environment)?;
+    let symbol = value_to_symbol(&symbol)?;
+    Scope::get(&environment, &symbol)
+        .ok_or_else(|| RuntimeError::UnboundVariable(symbol.to_string()))
+}
+
+/// Sets the value of an existing binding like `set!` does
+pub fn environment_assign(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [environment, symbol, value] = exactly(params)?;
+    let environment = value_to_environment(&environment)?;
+    let symbol = value_to_symbol(&symbol)?;
+    Scope::set(&environment, &symbol, value)
+        .map_err(|_| RuntimeError::UnboundVariable(symbol.to_string()))?;
+    Ok(Value::Unspecified)
+}
+
+/// Reads the first datum written in the string, returns the eof object when there is none
+pub fn read(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [text] = exactly(values_to_strings(&params)?)?;
+    let source = Source::new("<read>", &text);
+    let tokens = tokenizer::tokenize(&text).map_err(|errors| {
+        let (error, span) = &errors[0];
+        let location = source.location(span.clone());
+        RuntimeError::InvalidSyntax(format!("{error} at {location}"))
+    })?;
+    let expr = parser::parse(tokens.into_iter(), &source).map_err(|errors| {
+        let error = &errors[0];
+        RuntimeError::InvalidSyntax(format!("{} at {}", error.description(), error.location()))
+    })?;
+    match expr {
+        Some(expr) => crate::eval::datum(&Rc::new(expr)),
+        None => Ok(Value::Eof),
+    }
+}
+
+/// Evaluates data as an expression in the environment
+pub fn eval(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [expr, environment] = exactly(params)?;
+    let environment = value_to_environment(&environment)?;
+    crate::eval::evaluate(&crate::eval::expression(&expr)?, &environment)
+}
+
 pub fn zip(params: Vec<Value>) -> Result<Value, RuntimeError> {
     let lists = values_to_vecs(params.into_iter())?;
-    let zipped = zip_vecs(&lists).map(|i| Value::List(i)).collect();
+    let zipped = zip_vecs(&lists).map(Value::List).collect();
     Ok(Value::List(zipped))
 }
 
 pub fn read_file(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.len() != 1 {
-        return Err(RuntimeError::WrongNumberOfAgumentsPassed);
+        return Err(arity_error(Arity::Exactly(1), params.len()));
     }
     let file_name = &values_to_strings(&params)?[0];
-    let content = std::fs::read_to_string(file_name.as_ref()).map_err(|_| RuntimeError::IO)?;
+    let content = std::fs::read_to_string(file_name.as_ref()).map_err(|e| RuntimeError::IO {
+        kind: e.kind(),
+        path: file_name.to_string(),
+    })?;
     Ok(Value::String(Rc::new(content)))
 }
 
 pub fn split_string(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.len() != 1 {
-        return Err(RuntimeError::WrongNumberOfAgumentsPassed);
+        return Err(arity_error(Arity::Exactly(1), params.len()));
     }
     let string = &values_to_strings(&params)?[0];
 
@@ -283,9 +965,7 @@ pub fn split_string(params: Vec<Value>) -> Result<Value, RuntimeError> {
 }
 
 pub fn split_string_with(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let [string, sep] = &values_to_strings(&params)?
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
+    let [string, sep] = &exactly(values_to_strings(&params)?)?;
 
     let strings: Vec<_> = string
         .split(sep.as_ref())
@@ -295,13 +975,11 @@ pub fn split_string_with(params: Vec<Value>) -> Result<Value, RuntimeError> {
 }
 
 pub fn substring(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let string = values_to_strings(&params[..1])?
-        .first()
-        .ok_or(RuntimeError::WrongNumberOfAgumentsPassed)?
-        .clone();
-    let [start, end] = values_to_ints(&params[1..])?
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
+    if params.len() != 3 {
+        return Err(arity_error(Arity::Exactly(3), params.len()));
+    }
+    let string = values_to_strings(&params[..1])?.remove(0);
+    let [start, end] = exactly(values_to_ints(&params[1..])?)?;
 
     let start = if start < 0 {
         string.len() - start.unsigned_abs() as usize + 1
@@ -320,9 +998,7 @@ pub fn substring(params: Vec<Value>) -> Result<Value, RuntimeError> {
 }
 
 pub fn string_ref(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let [string, n] = params
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
+    let [string, n] = exactly(params)?;
 
     let string = match string {
         Value::String(s) => Ok(s),
@@ -334,31 +1010,170 @@ pub fn string_ref(params: Vec<Value>) -> Result<Value, RuntimeError> {
         v => Err(RuntimeError::NumberExpected(v)),
     }?;
 
-    string
-        .chars()
-        .nth(n as usize)
-        .map(|ch| Ok(Value::String(Rc::new(ch.to_string()))))
-        .unwrap_or(Ok(Value::Nil))
+    usize::try_from(n)
+        .ok()
+        .and_then(|i| string.chars().nth(i))
+        .map(Value::Char)
+        .ok_or(RuntimeError::IndexOutOfRange(n))
 }
 
-pub fn parse_int(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let [string] = values_to_strings(&params)?
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
+pub fn string_to_list(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [string] = exactly(values_to_strings(&params)?)?;
+    Ok(Value::List(string.chars().map(Value::Char).collect()))
+}
+
+pub fn list_to_string(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list] = exactly(values_to_vecs(params.into_iter())?)?;
+    let string = values_to_chars(&list)?.into_iter().collect();
+    Ok(Value::String(Rc::new(string)))
+}
+
+fn single_char(params: Vec<Value>) -> Result<char, RuntimeError> {
+    let [c] = exactly(values_to_chars(&params)?)?;
+    Ok(c)
+}
+
+/// Simple case conversion: characters whose case mapping is not a single character are kept
+fn map_single_char<I: Iterator<Item = char>>(c: char, mapped: I) -> char {
+    let mut mapped = mapped;
+    match (mapped.next(), mapped.next()) {
+        (Some(m), None) => m,
+        _ => c,
+    }
+}
+
+fn downcase(c: char) -> char {
+    map_single_char(c, c.to_lowercase())
+}
+
+pub fn is_char(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    Ok(Value::Bool(matches!(param, Value::Char(_))))
+}
+
+pub fn char_to_integer(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::Int(single_char(params)? as i64))
+}
+
+pub fn integer_to_char(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [code] = exactly(values_to_ints(&params)?)?;
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(Value::Char)
+        .ok_or(RuntimeError::InvalidCodePoint(code))
+}
+
+pub fn char_upcase(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let c = single_char(params)?;
+    Ok(Value::Char(map_single_char(c, c.to_uppercase())))
+}
+
+pub fn char_downcase(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::Char(downcase(single_char(params)?)))
+}
+
+pub fn is_char_alphabetic(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::Bool(single_char(params)?.is_alphabetic()))
+}
 
-    let int = string.parse::<i64>().ok().unwrap_or_default();
-    Ok(Value::Int(int))
+/// Decimal digits only, so that every numeric character has a `digit-value`
+pub fn is_char_numeric(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::Bool(single_char(params)?.is_ascii_digit()))
+}
+
+pub fn is_char_whitespace(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(Value::Bool(single_char(params)?.is_whitespace()))
+}
+
+pub fn digit_value(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    Ok(single_char(params)?
+        .to_digit(10)
+        .map(|d| Value::Int(d as i64))
+        .unwrap_or(Value::Bool(false)))
+}
+
+pub fn string_to_number(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (string, radix) = match params.as_slice() {
+        [string] => (string, 10),
+        [string, Value::Int(radix)] => (string, *radix),
+        [_, radix] => return Err(RuntimeError::NumberExpected(radix.clone())),
+        _ => return Err(arity_error(Arity::Between(1, 2), params.len())),
+    };
+    let string = values_to_strings(std::slice::from_ref(string))?;
+    let radix = check_radix(radix)?;
+
+    Ok(match tokenizer::parse_number(&string[0], radix) {
+        Ok(Number::Int(i)) => Value::Int(i),
+        Ok(Number::Float(f)) => Value::Float(f),
+        Err(_) => Value::Bool(false),
+    })
+}
+
+fn check_radix(radix: i64) -> Result<u32, RuntimeError> {
+    match radix {
+        2..=36 => Ok(radix as u32),
+        _ => Err(RuntimeError::InvalidRadix(radix)),
+    }
+}
+
+fn int_to_string(int: i64, radix: u32) -> String {
+    let mut digits = Vec::new();
+    let mut rest = int.unsigned_abs();
+    loop {
+        digits.push(std::char::from_digit((rest % radix as u64) as u32, radix).unwrap());
+        rest /= radix as u64;
+        if rest == 0 {
+            break;
+        }
+    }
+    if int < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+pub fn number_to_string(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (number, radix, precision) = match params.as_slice() {
+        [number] => (number, Value::Int(10), None),
+        [number, radix] => (number, radix.clone(), None),
+        [number, radix, precision] => (number, radix.clone(), Some(precision.clone())),
+        _ => return Err(arity_error(Arity::Between(1, 3), params.len())),
+    };
+    let radix = match radix {
+        Value::Int(radix) => check_radix(radix)?,
+        v => return Err(RuntimeError::NumberExpected(v)),
+    };
+    let precision = match precision {
+        None => None,
+        Some(Value::Int(p)) if p >= 0 => Some(p as usize),
+        Some(v) => return Err(RuntimeError::NumberExpected(v)),
+    };
+
+    let ans = match (number, precision) {
+        (Value::Int(i), None) => int_to_string(*i, radix),
+        (Value::Int(_) | Value::Float(_), _) if radix != 10 => {
+            return Err(RuntimeError::InvalidRadix(radix as i64));
+        }
+        // with a precision integers are written as decimals too
+        (Value::Int(i), Some(precision)) => format!("{:.precision$}", *i as f64),
+        (Value::Float(f), Some(precision)) if f.is_finite() => format!("{f:.precision$}"),
+        (Value::Float(f), _) => float_to_string(*f),
+        (v, _) => return Err(RuntimeError::NumberExpected(v.clone())),
+    };
+    Ok(Value::String(Rc::new(ans)))
 }
 
 pub fn to_string(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.len() != 1 {
-        return Err(RuntimeError::WrongNumberOfAgumentsPassed);
+        return Err(arity_error(Arity::Exactly(1), params.len()));
     }
 
     let ans = match &params[0] {
         Value::Bool(b) => format!("{b}"),
         Value::Int(i) => format!("{i}"),
         Value::Float(f) => format!("{f}"),
+        Value::Char(c) => c.to_string(),
         Value::String(s) => s.as_ref().clone(),
         val => return Err(RuntimeError::StringExpected(val.clone())),
     };
@@ -367,30 +1182,56 @@ pub fn to_string(params: Vec<Value>) -> Result<Value, RuntimeError> {
 
 pub fn length(params: Vec<Value>) -> Result<Value, RuntimeError> {
     if params.len() != 1 {
-        return Err(RuntimeError::WrongNumberOfAgumentsPassed);
+        return Err(arity_error(Arity::Exactly(1), params.len()));
     }
 
     let ans = match &params[0] {
         Value::String(s) => s.len() as i64,
         Value::List(l) => l.len() as i64,
+        Value::Vector(v) => v.borrow().len() as i64,
         val => return Err(RuntimeError::StringExpected(val.clone())),
     };
     Ok(Value::Int(ans))
 }
 
+/// First false value, otherwise the last value or `#t` when there are none
 pub fn and(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let params = values_to_bools(&params)?;
-    Ok(Value::Bool(params.into_iter().all(|v| v)))
+    let mut ans = Value::Bool(true);
+    for param in params {
+        if !param.truthy() {
+            return Ok(param);
+        }
+        ans = param;
+    }
+    Ok(ans)
 }
 
+/// First true value, otherwise `#f`
 pub fn or(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let params = values_to_bools(&params)?;
-    Ok(Value::Bool(params.into_iter().any(|v| v)))
+    Ok(params
+        .into_iter()
+        .find(Value::truthy)
+        .unwrap_or(Value::Bool(false)))
 }
 
 pub fn not(params: Vec<Value>) -> Result<Value, RuntimeError> {
-    let [param] = values_to_bools(&params)?
-        .try_into()
-        .map_err(|_| RuntimeError::WrongNumberOfAgumentsPassed)?;
-    Ok(Value::Bool(!param))
+    let [param] = exactly(params)?;
+    Ok(Value::Bool(!param.truthy()))
+}
+
+pub fn is_null(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    Ok(Value::Bool(matches!(param, Value::List(l) if l.is_empty())))
+}
+
+pub fn eof_object(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    if !params.is_empty() {
+        return Err(arity_error(Arity::Exactly(0), params.len()));
+    }
+    Ok(Value::Eof)
+}
+
+pub fn is_eof_object(params: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [param] = exactly(params)?;
+    Ok(Value::Bool(matches!(param, Value::Eof)))
 }
@@ -1,81 +1,355 @@
-use crate::{parser::Expr, scope::Scope, special_forms::*, tokenizer::Token, value::Value};
-use std::{fmt::Display, rc::Rc};
+use crate::{
+    parser::{Expr, ExprKind},
+    scope::Scope,
+    source::{Location, Source},
+    special_forms::*,
+    tokenizer::{Number, Token},
+    value::{Sharing, Value},
+};
+use std::{cell::RefCell, collections::HashSet, fmt::Display, rc::Rc};
 
 #[derive(Debug)]
 pub enum RuntimeError {
-    NotProcedure,
+    NotProcedure(Value),
     UnboundVariable(String),
     IllFormedExpression,
-    IllFormedSpecialForm,
+    IllFormedSpecialForm(&'static str),
     ParameterMustBeIdentifier,
     OperatorIsNotProcedure,
     NumberExpected(Value),
     StringExpected(Value),
-    BooleanExpected(Value),
-    WrongNumberOfAgumentsPassed,
+    CharExpected(Value),
+    WrongNumberOfAgumentsPassed {
+        expected: Arity,
+        passed: usize,
+    },
     IdentifierExpected,
     DivideByZero,
+    IntegerOverflow,
+    InvalidCodePoint(i64),
     ListExpected(Value),
-    IO,
+    VectorExpected(Value),
+    HashTableExpected(Value),
+    SetExpected(Value),
+    SymbolExpected(Value),
+    /// Value that has no representation as an expression, e.g. a procedure passed to `eval`
+    DatumExpected(Value),
+    /// Text passed to `read` is not a valid datum, the message says why and where
+    InvalidSyntax(String),
+    EnvironmentExpected(Value),
+    RecordExpected(String, Value),
+    KeyNotFound(Value),
+    IndexOutOfRange(i64),
+    IncomparableValues(Value, Value),
+    InvalidRadix(i64),
+    IO {
+        kind: std::io::ErrorKind,
+        path: String,
+    },
+    /// Error together with the place where it was raised and the calls that led to it
+    Traced(Box<RuntimeError>, Box<Trace>),
+}
+
+/// Number of frames printed unless the full backtrace is requested with `{:#}`
+const BACKTRACE_FRAMES: usize = 10;
+
+/// Call of a procedure that was interrupted by an error
+#[derive(Debug)]
+pub struct Frame {
+    pub procedure: String,
+    pub arguments: String,
+    pub call_site: Option<Location>,
+}
+
+/// Location of an error and the backtrace of calls, most recent call first
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub location: Option<Location>,
+    pub backtrace: Vec<Frame>,
+}
+
+impl Trace {
+    /// Writes the error message at its location followed by the backtrace. The alternate flag
+    /// prints all frames instead of just the top ones.
+    pub fn fmt_error(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        error: &dyn Display,
+    ) -> std::fmt::Result {
+        // the innermost frame is the procedure that failed
+        let message = match self.backtrace.first() {
+            Some(frame) => format!("{}: {error}", frame.procedure),
+            None => error.to_string(),
+        };
+        match &self.location {
+            Some(location) => location.fmt_message(f, &message)?,
+            None => write!(f, "{message}")?,
+        }
+        if self.backtrace.is_empty() {
+            return Ok(());
+        }
+
+        let shown = if f.alternate() {
+            self.backtrace.len()
+        } else {
+            BACKTRACE_FRAMES
+        };
+        write!(f, "\nbacktrace (most recent call first):")?;
+        for (i, frame) in self.backtrace.iter().take(shown).enumerate() {
+            write!(f, "\n{i:>4}: {} with {}", frame.procedure, frame.arguments)?;
+            if let Some(call_site) = &frame.call_site {
+                write!(f, "\n          at {call_site}")?;
+            }
+        }
+        if self.backtrace.len() > shown {
+            write!(
+                f,
+                "\n      ... {} more frames",
+                self.backtrace.len() - shown
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl RuntimeError {
+    /// Splits the error into the underlying error and its trace
+    pub fn into_parts(self) -> (RuntimeError, Trace) {
+        match self {
+            RuntimeError::Traced(error, trace) => (*error, *trace),
+            error => (error, Trace::default()),
+        }
+    }
+
+    /// Attaches the location of the expression being evaluated. The location of the error is
+    /// only set once, at the innermost expression, and so are the call sites of the frames.
+    pub fn at(self, location: &Location) -> Self {
+        let (error, mut trace) = self.into_parts();
+        trace.location.get_or_insert_with(|| location.clone());
+        trace
+            .backtrace
+            .iter_mut()
+            .rev()
+            .take_while(|frame| frame.call_site.is_none())
+            .for_each(|frame| frame.call_site = Some(location.clone()));
+        RuntimeError::Traced(Box::new(error), Box::new(trace))
+    }
+
+    /// Records the call of the procedure the error is propagated out of
+    pub fn in_frame(self, procedure: String, arguments: String) -> Self {
+        let (error, mut trace) = self.into_parts();
+        trace.backtrace.push(Frame {
+            procedure,
+            arguments,
+            call_site: None,
+        });
+        RuntimeError::Traced(Box::new(error), Box::new(trace))
+    }
 }
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            RuntimeError::NotProcedure(v) => write!(f, "{} is not a procedure", readable(v)),
+            RuntimeError::UnboundVariable(name) => write!(f, "unbound variable `{name}`"),
+            RuntimeError::IllFormedExpression => write!(f, "ill-formed expression"),
+            RuntimeError::IllFormedSpecialForm(name) => {
+                write!(f, "ill-formed special form `{name}`")
+            }
+            RuntimeError::ParameterMustBeIdentifier => write!(f, "parameter must be an identifier"),
+            RuntimeError::OperatorIsNotProcedure => write!(f, "operator is not a procedure"),
+            RuntimeError::NumberExpected(v) => write!(f, "expected a number, got {}", readable(v)),
+            RuntimeError::StringExpected(v) => write!(f, "expected a string, got {}", readable(v)),
+            RuntimeError::CharExpected(v) => {
+                write!(f, "expected a character, got {}", readable(v))
+            }
+            RuntimeError::WrongNumberOfAgumentsPassed { expected, passed } => write!(
+                f,
+                "wrong number of arguments: expected {expected}, got {passed}"
+            ),
+            RuntimeError::IdentifierExpected => write!(f, "expected an identifier"),
+            RuntimeError::DivideByZero => write!(f, "division by zero"),
+            RuntimeError::IntegerOverflow => write!(f, "integer overflow"),
+            RuntimeError::InvalidCodePoint(code) => {
+                write!(f, "{code} is not a valid Unicode code point")
+            }
+            RuntimeError::ListExpected(v) => write!(f, "expected a list, got {}", readable(v)),
+            RuntimeError::VectorExpected(v) => write!(f, "expected a vector, got {}", readable(v)),
+            RuntimeError::HashTableExpected(v) => {
+                write!(f, "expected a hash table, got {}", readable(v))
+            }
+            RuntimeError::SetExpected(v) => write!(f, "expected a set, got {}", readable(v)),
+            RuntimeError::SymbolExpected(v) => write!(f, "expected a symbol, got {}", readable(v)),
+            RuntimeError::DatumExpected(v) => {
+                write!(f, "cannot evaluate {}, expected a datum", readable(v))
+            }
+            RuntimeError::InvalidSyntax(message) => write!(f, "invalid syntax: {message}"),
+            RuntimeError::EnvironmentExpected(v) => {
+                write!(f, "expected an environment, got {}", readable(v))
+            }
+            RuntimeError::RecordExpected(name, v) => {
+                write!(f, "expected a record of type {name}, got {}", readable(v))
+            }
+            RuntimeError::KeyNotFound(key) => {
+                write!(f, "key {} not found in hash table", readable(key))
+            }
+            RuntimeError::IndexOutOfRange(index) => write!(f, "index {index} is out of range"),
+            RuntimeError::IncomparableValues(a, b) => {
+                write!(f, "cannot compare {} with {}", readable(a), readable(b))
+            }
+            RuntimeError::InvalidRadix(radix) => write!(f, "invalid radix {radix}"),
+            RuntimeError::IO { kind, path } => write!(f, "cannot read `{path}`: {kind}"),
+            RuntimeError::Traced(error, trace) => trace.fmt_error(f, error),
+        }
+    }
+}
+
+/// Summary of arguments of a built-in call, whose values are not kept for the backtrace
+pub fn count_arguments(passed: usize) -> String {
+    match passed {
+        1 => "1 argument".to_string(),
+        n => format!("{n} arguments"),
+    }
+}
+
+/// Number of arguments a procedure accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arity::Exactly(n) => write!(f, "{n}"),
+            Arity::AtLeast(n) => write!(f, "at least {n}"),
+            Arity::Between(min, max) => write!(f, "{min} to {max}"),
+        }
     }
 }
 
+/// Value as it is shown in error messages, written so that strings and characters can be told
+/// apart from numbers and symbols
+fn readable(value: &Value) -> String {
+    value.written(Sharing::Cycles).to_string()
+}
+
 pub fn evaluate(expr: &Rc<Expr>, scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
-    let expr = expr.as_ref();
-    match expr {
-        Expr::Token(token) => match token {
+    evaluate_kind(&expr.kind, scope).map_err(|e| e.at(&expr.location))
+}
+
+fn evaluate_kind(kind: &ExprKind, scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    match kind {
+        ExprKind::Token(token) => match token {
             Token::Symbol(symbol) => Scope::get(scope, symbol)
                 .ok_or_else(|| RuntimeError::UnboundVariable(symbol.clone())),
-            Token::Int(i) => Ok(Value::Int(*i)),
-            Token::Float(f) => Ok(Value::Float(*f)),
+            Token::Number(Number::Int(i)) => Ok(Value::Int(*i)),
+            Token::Number(Number::Float(f)) => Ok(Value::Float(*f)),
             Token::StringLiteral(s) => Ok(Value::String(s.clone())),
             Token::Bool(b) => Ok(Value::Bool(*b)),
-            _ => todo!(),
+            Token::Char(c) => Ok(Value::Char(*c)),
+            // Following case should not happen because brackets and quotes are converted to
+            // nested lists and whitespace and comments are ignored in tokenizer
+            Token::LBracket
+            | Token::VectorStart
+            | Token::QuoteMark
+            | Token::DatumComment
+            | Token::RBracket
+            | Token::WhiteSpace
+            | Token::Comment => panic!(),
         },
-        Expr::List(list) => match list.first() {
+        // vector literals are constants, their elements are not evaluated
+        ExprKind::Vector(items) => {
+            let items = items.iter().map(datum).collect::<Result<_, _>>()?;
+            Ok(Value::new_vector(items))
+        }
+        ExprKind::List(list) => match list.first() {
             None => Err(RuntimeError::IllFormedExpression),
             Some(head) => {
                 let tail = &list[1..];
-                match head.as_ref() {
-                    Expr::List(_) => invoke_lambda(head, tail, scope),
-                    Expr::Token(head_token) => match head_token {
-                        // list of commands - evaluate all and return last one
-                        Token::Begin => begin(tail, scope),
-                        // if (cond) (if_true_expr) (else_expr)
-                        Token::If => if_statement(tail, scope),
-                        // create custom procedure
-                        Token::Lambda => lambda(tail, scope),
-                        // set value of variable
-                        Token::Set => {
-                            define_variable(tail, scope, DefineBehavior::SetValueOfExisting)
-                        }
-                        // create new variable
-                        Token::Define => define(tail, scope),
-                        // invoke procedure or built-in function
-                        Token::Symbol(symbol) => invoke_named_function(tail, scope, symbol),
-                        // loop
-                        Token::Do => do_loop(tail, scope),
-                        Token::Int(_)
-                        | Token::Float(_)
-                        | Token::StringLiteral(_)
-                        | Token::Bool(_) => Err(RuntimeError::OperatorIsNotProcedure),
-                        // Following case should not happen because brackets are converted to nested lists
-                        // and whitespace and comments are ignored in tokenizer
-                        Token::LBracket | Token::RBracket | Token::WhiteSpace | Token::Comment => {
-                            panic!()
-                        }
-                    },
+                match &head.kind {
+                    // invoke special form, procedure or built-in function bound to the symbol
+                    ExprKind::Token(Token::Symbol(symbol)) => {
+                        invoke_named_function(tail, scope, symbol)
+                    }
+                    ExprKind::List(_) => invoke_lambda(head, tail, scope),
+                    ExprKind::Token(_) | ExprKind::Vector(_) => {
+                        Err(RuntimeError::OperatorIsNotProcedure)
+                    }
                 }
             }
         },
     }
 }
 
+/// Converts literal expression to data without evaluating it
+pub fn datum(expr: &Rc<Expr>) -> Result<Value, RuntimeError> {
+    match &expr.kind {
+        ExprKind::Token(Token::Number(Number::Int(i))) => Ok(Value::Int(*i)),
+        ExprKind::Token(Token::Number(Number::Float(f))) => Ok(Value::Float(*f)),
+        ExprKind::Token(Token::StringLiteral(s)) => Ok(Value::String(s.clone())),
+        ExprKind::Token(Token::Bool(b)) => Ok(Value::Bool(*b)),
+        ExprKind::Token(Token::Char(c)) => Ok(Value::Char(*c)),
+        ExprKind::Token(Token::Symbol(s)) => Ok(Value::Symbol(Rc::new(s.clone()))),
+        ExprKind::Token(_) => Err(RuntimeError::IllFormedExpression),
+        ExprKind::List(items) => Ok(Value::List(
+            items.iter().map(datum).collect::<Result<_, _>>()?,
+        )),
+        ExprKind::Vector(items) => Ok(Value::new_vector(
+            items.iter().map(datum).collect::<Result<_, _>>()?,
+        )),
+    }
+}
+
+/// Converts data to an expression, the inverse of `datum`. Data has no place in the program text,
+/// so all parts of the expression are located at the start of an empty `<eval>` source.
+pub fn expression(value: &Value) -> Result<Rc<Expr>, RuntimeError> {
+    let location = Source::new("<eval>", "").end();
+    to_expression(value, &location, &mut HashSet::new())
+}
+
+/// `path` holds the vectors that contain the value, so that circular vectors are rejected
+/// instead of converted forever
+fn to_expression(
+    value: &Value,
+    location: &Location,
+    path: &mut HashSet<*const RefCell<Vec<Value>>>,
+) -> Result<Rc<Expr>, RuntimeError> {
+    let kind = match value {
+        Value::Int(i) => ExprKind::Token(Token::Number(Number::Int(*i))),
+        Value::Float(f) => ExprKind::Token(Token::Number(Number::Float(*f))),
+        Value::String(s) => ExprKind::Token(Token::StringLiteral(s.clone())),
+        Value::Bool(b) => ExprKind::Token(Token::Bool(*b)),
+        Value::Char(c) => ExprKind::Token(Token::Char(*c)),
+        Value::Symbol(s) => ExprKind::Token(Token::Symbol(s.to_string())),
+        Value::List(items) => ExprKind::List(
+            items
+                .iter()
+                .map(|item| to_expression(item, location, path))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Vector(vector) => {
+            if !path.insert(Rc::as_ptr(vector)) {
+                return Err(RuntimeError::DatumExpected(value.clone()));
+            }
+            let items = vector
+                .borrow()
+                .iter()
+                .map(|item| to_expression(item, location, path))
+                .collect::<Result<_, _>>()?;
+            path.remove(&Rc::as_ptr(vector));
+            ExprKind::Vector(items)
+        }
+        v => return Err(RuntimeError::DatumExpected(v.clone())),
+    };
+    Ok(Rc::new(Expr {
+        kind,
+        location: location.clone(),
+    }))
+}
+
 pub fn evaluate_list(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Option<Value>, RuntimeError> {
     exprs
         .iter()
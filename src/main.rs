@@ -2,32 +2,79 @@ mod builtin;
 mod eval;
 mod parser;
 mod scope;
+mod source;
 mod special_forms;
 mod tokenizer;
 mod value;
+use itertools::Itertools;
 use std::{fmt::Debug, rc::Rc};
 
 #[derive(Debug)]
 enum Error {
-    Lexing(Vec<(tokenizer::LexingError, logos::Span)>),
-    Parsing(parser::ParsingError),
-    Runtime(eval::RuntimeError),
+    Lexing(Vec<(tokenizer::LexingError, source::Location)>),
+    Parsing(Vec<parser::ParsingError>),
+    Runtime(eval::RuntimeError, Box<eval::Trace>),
+}
+
+impl From<eval::RuntimeError> for Error {
+    fn from(error: eval::RuntimeError) -> Self {
+        let (error, trace) = error.into_parts();
+        Error::Runtime(error, Box::new(trace))
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::Lexing(les) => std::fmt::Display::fmt(&les[0].0, f), // TODO: print ALL lexing errors
-            Error::Parsing(e) => std::fmt::Display::fmt(&e, f),
-            Error::Runtime(e) => std::fmt::Display::fmt(&e, f),
+            Error::Lexing(les) => {
+                for (i, (error, location)) in les.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    location.fmt_message(f, error)?;
+                }
+                Ok(())
+            }
+            Error::Parsing(pes) => {
+                for (i, error) in pes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    std::fmt::Display::fmt(error, f)?;
+                }
+                Ok(())
+            }
+            Error::Runtime(e, trace) => trace.fmt_error(f, e),
         }
     }
 }
 
-fn eval(src: &str, scope: &Rc<scope::Scope>) -> Result<value::Value, Error> {
-    let tokens = tokenizer::tokenize(src).map_err(Error::Lexing)?;
-    let expr = parser::parse(tokens.into_iter()).map_err(Error::Parsing)?;
-    eval::evaluate(&Rc::new(expr), scope).map_err(Error::Runtime)
+fn parse(source: &Rc<source::Source>) -> Result<Vec<Rc<parser::Expr>>, Error> {
+    let tokens = tokenizer::tokenize(&source.text).map_err(|les| {
+        let les = les.into_iter().sorted_by_key(|(_, span)| span.start);
+        Error::Lexing(les.map(|(e, span)| (e, source.location(span))).collect())
+    })?;
+    let exprs = parser::parse_program(tokens.into_iter(), source).map_err(Error::Parsing)?;
+    Ok(exprs.into_iter().map(Rc::new).collect())
+}
+
+/// Evaluates all top-level forms in order and returns value of the last one
+fn eval(source: &Rc<source::Source>, scope: &Rc<scope::Scope>) -> Result<value::Value, Error> {
+    parse(source)?
+        .iter()
+        .try_fold(value::Value::Unspecified, |_, expr| {
+            Ok(eval::evaluate(expr, scope)?)
+        })
+}
+
+/// Formats the error for the user. Backtraces are cut to the top frames unless the
+/// `RISP_BACKTRACE` environment variable is set to `full`.
+fn report(error: &Error) -> String {
+    if std::env::var("RISP_BACKTRACE").is_ok_and(|value| value == "full") {
+        format!("{error:#}")
+    } else {
+        format!("{error}")
+    }
 }
 
 fn interactive_shell(scope: &Rc<scope::Scope>) {
@@ -37,11 +84,22 @@ fn interactive_shell(scope: &Rc<scope::Scope>) {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(&line).unwrap();
-                let ans = eval(&line, scope);
-                match ans {
-                    Err(e) => println!("ERROR: {}", e),
-                    Ok(value::Value::Nil) => {}
-                    Ok(v) => println!("{}", v),
+                let exprs = match parse(&source::Source::new("<repl>", &line)) {
+                    Ok(exprs) => exprs,
+                    Err(e) => {
+                        println!("ERROR: {}", report(&e));
+                        continue;
+                    }
+                };
+                for expr in exprs {
+                    match eval::evaluate(&expr, scope) {
+                        Err(e) => {
+                            println!("ERROR: {}", report(&Error::from(e)));
+                            break;
+                        }
+                        Ok(value::Value::Unspecified) => {}
+                        Ok(v) => println!("{}", v.written(value::Sharing::Cycles)),
+                    }
                 }
             }
             Err(rustyline::error::ReadlineError::Interrupted) => {
@@ -65,10 +123,10 @@ fn main() {
     let global_scope = scope::Scope::global();
     match file_path {
         Some(file_path) => {
-            let src = std::fs::read_to_string(file_path).unwrap();
-            match eval(&src, &global_scope) {
+            let src = std::fs::read_to_string(&file_path).unwrap();
+            match eval(&source::Source::new(&file_path, &src), &global_scope) {
                 Ok(_) => {}
-                Err(e) => eprintln!("{e}"),
+                Err(e) => eprintln!("{}", report(&e)),
             }
         }
         None => interactive_shell(&global_scope),
@@ -81,7 +139,7 @@ mod test {
 
     fn run(src: &str) -> Result<(value::Value, Rc<scope::Scope>), Error> {
         let global_scope = crate::scope::Scope::global();
-        let ans = eval(src, &global_scope)?;
+        let ans = eval(&source::Source::new("<test>", src), &global_scope)?;
         Ok((ans, global_scope))
     }
 
@@ -96,7 +154,7 @@ mod test {
     fn pointer_to_build_in_func() {
         let src = "(define foo +)";
         let ans = run(src);
-        assert!(matches!(ans, Ok((value::Value::Nil, _))));
+        assert!(matches!(ans, Ok((value::Value::Unspecified, _))));
         let global_scope = ans.expect("Ok value expected").1;
         let foo = scope::Scope::get(&global_scope, "foo").expect("foo variable expected");
         assert!(matches!(foo, value::Value::BuiltIn(_)));
@@ -159,6 +217,90 @@ mod test {
         );
     }
 
+    #[test]
+    fn special_forms_are_rebindable() {
+        let examples = [
+            // shadowing a keyword by a parameter
+            ("((lambda (if) (+ if 1)) 41)", "42"),
+            // renaming a keyword
+            ("(begin (define when if) (when #t 'yes 'no))", "yes"),
+            // keywords are ordinary symbols in quoted data
+            ("'(define x (if a b))", "(define x (if a b))"),
+            ("(quote quote)", "quote"),
+            ("if", "<special form if>"),
+            ("(begin (define (f list) (list 1 2)) (f +))", "3"),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+
+        let ans = run("(begin (define if 5) (if #t 1 2))").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::NotProcedure(_), _)
+        ));
+    }
+
+    #[test]
+    fn comments() {
+        let src = "#!/usr/bin/env risp
+            (list
+                1 ; line comment
+                #| block comment
+                   #| nested (block comment) |#
+                   still commented |#
+                2
+                #;(this whole (datum) is ignored)
+                #; #;3 4
+                5 #| tail |#)";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(ans.to_string(), "(1 2 5)");
+
+        let ans = run("(list 1 #| λ größe #| ½ |# |# 2)")
+            .expect("Ok value expected")
+            .0;
+        assert_eq!(ans.to_string(), "(1 2)");
+
+        let ans = run("#;(ignored) 42").expect("Ok value expected").0;
+        assert!(matches!(ans, value::Value::Int(42)));
+
+        match run("(+ 1 #| unterminated") {
+            Err(Error::Lexing(les)) => {
+                assert_eq!(les[0].0, tokenizer::LexingError::UnexpectedEof)
+            }
+            _ => panic!("lexing error expected"),
+        }
+    }
+
+    #[test]
+    fn multiple_top_level_forms() {
+        let src = "
+            (define (square x) (* x x))
+            (define x 3)
+            #;(this is ignored)
+            (set! x (square x))
+            (+ x 1)
+            #;(so is this)";
+        let ans = run(src).expect("Ok value expected").0;
+        assert!(matches!(ans, value::Value::Int(10)));
+
+        let ans = run("(define x 1) x (+ x 1)").expect("Ok value expected").0;
+        assert!(matches!(ans, value::Value::Int(2)));
+
+        let ans = run("  ; nothing but a comment")
+            .expect("Ok value expected")
+            .0;
+        assert!(matches!(ans, value::Value::Unspecified));
+
+        let ans = run("(define x 1) (+ x \"a\") (set! x 2)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::NumberExpected(_), _)
+        ));
+    }
+
     #[test]
     fn zeronary_ops() {
         let examples = [("(+)", 0), ("(*)", 1)];
@@ -241,11 +383,145 @@ mod test {
             let ans = run(src).expect_err("Err value expected");
             assert!(matches!(
                 ans,
-                Error::Runtime(eval::RuntimeError::DivideByZero)
+                Error::Runtime(eval::RuntimeError::DivideByZero, _)
             ))
         }
     }
 
+    #[test]
+    fn bitwise_operations() {
+        let examples = [
+            ("(bitwise-and #b1100 #b1010)", 0b1000),
+            ("(bitwise-and)", -1),
+            ("(bitwise-or #b1100 #b1010 1)", 0b1111),
+            ("(bitwise-xor #b1100 #b1010)", 0b0110),
+            ("(bitwise-not 5)", -6),
+            ("(arithmetic-shift 1 10)", 1024),
+            ("(arithmetic-shift -8 -2)", -2),
+            ("(arithmetic-shift 5 -100)", 0),
+            ("(bit-count #b10110)", 3),
+            ("(bit-count -1)", 0),
+            ("(first-set-bit #b10100)", 2),
+            ("(first-set-bit 0)", -1),
+            ("(integer-length 255)", 8),
+            ("(integer-length -256)", 8),
+            ("(integer-length 0)", 0),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            match ans {
+                value::Value::Int(ans) => assert_eq!(ans, expected, "{}", src),
+                _ => panic!("{src}"),
+            }
+        }
+
+        let ans = run("(list (bit-set? 2 #b100) (bit-set? 1 #b100) (bit-set? 100 -1))");
+        assert_eq!(
+            ans.expect("Ok value expected").0.to_string(),
+            "(true false true)"
+        );
+
+        let ans = run("(arithmetic-shift 1 63)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::IntegerOverflow, _)
+        ));
+        let ans = run("(bit-set? -1 1)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::IndexOutOfRange(-1), _)
+        ));
+    }
+
+    #[test]
+    fn identifiers() {
+        let src = r#"
+            (begin
+                (define a_b 1)
+                (define %internal 2)
+                (define &rest 3)
+                (define set!x1 4)
+                (define list->vector* 5)
+                (define x:y^z~ 6)
+                (define $dollar@at 7)
+                (define ... 8)
+                (define .. 9)
+                (define -> 10)
+                (define +x 11)
+                (define -.y 12)
+                (define λ 13)
+                (define größe 14)
+                (define |odd symbol| 15)
+                (define |a\|b\x41;| 16)
+                (list a_b %internal &rest set!x1 list->vector* x:y^z~ $dollar@at ... .. -> +x -.y
+                      λ größe |odd symbol| |a\|bA| (- 100 83) + (+ 1 +1)))"#;
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(
+            ans.to_string(),
+            "(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 <built-in function +> 2)"
+        );
+
+        let ans = run("(quote |hello world|)").expect("Ok value expected").0;
+        assert!(matches!(ans, value::Value::Symbol(s) if s.as_str() == "hello world"));
+    }
+
+    #[test]
+    fn numeric_literals() {
+        let examples = [
+            ("#xFF", value::Value::Int(255)),
+            ("#b-1010", value::Value::Int(-10)),
+            ("#o17", value::Value::Int(15)),
+            ("#d#e10", value::Value::Int(10)),
+            ("+42", value::Value::Int(42)),
+            ("1_000_000", value::Value::Int(1_000_000)),
+            ("#e1.5e3", value::Value::Int(1500)),
+            ("1e9", value::Value::Float(1e9)),
+            ("-1.5E-3", value::Value::Float(-1.5e-3)),
+            (".5", value::Value::Float(0.5)),
+            ("#i#x10", value::Value::Float(16.0)),
+            ("+inf.0", value::Value::Float(f64::INFINITY)),
+            ("-inf.0", value::Value::Float(f64::NEG_INFINITY)),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            match (ans, expected) {
+                (value::Value::Int(ans), value::Value::Int(expected)) => {
+                    assert_eq!(ans, expected, "{}", src)
+                }
+                (value::Value::Float(ans), value::Value::Float(expected)) => {
+                    assert_eq!(ans, expected, "{}", src)
+                }
+                (ans, _) => panic!("{src} evaluated to unexpected {ans:?}"),
+            }
+        }
+
+        let ans = run("-nan.0").expect("Ok value expected").0;
+        assert!(matches!(ans, value::Value::Float(f) if f.is_nan()));
+    }
+
+    #[test]
+    fn invalid_numeric_literals() {
+        let examples = [
+            ("(+ 1 #xZZ)", 5..9),
+            ("#e1.5", 0..5),
+            ("1__0", 0..4),
+            ("#x#b1", 0..5),
+            ("99999999999999999999", 0..20),
+        ];
+
+        for (src, expected) in examples {
+            match run(src) {
+                Err(Error::Lexing(les)) => {
+                    assert_eq!(les[0].0, tokenizer::LexingError::NumberParseError, "{src}");
+                    assert_eq!(les[0].1.span, expected, "{src}");
+                }
+                _ => panic!("lexing error expected for {src}"),
+            }
+        }
+    }
+
     #[test]
     fn modulo() {
         let examples = [
@@ -268,7 +544,7 @@ mod test {
         let src = r#"
             (length
                 (->string
-                    (string->int
+                    (string->number
                         (substring
                             (apply string-concatenate
                                 (split-string-with
@@ -287,9 +563,102 @@ mod test {
         }
     }
 
+    #[test]
+    fn string_to_number() {
+        let examples = [
+            (r#"(string->number "42")"#, "42"),
+            (r#"(string->number "-1.5e2")"#, "-150"),
+            (r#"(string->number "ff" 16)"#, "255"),
+            (r##"(string->number "#xff" 2)"##, "255"),
+            (r##"(string->number "#b101")"##, "5"),
+            (r#"(string->number "12abc")"#, "false"),
+            (r#"(string->number "")"#, "false"),
+            (r#"(string->number "+-1.5")"#, "false"),
+            (r#"(string->number "--2.5")"#, "false"),
+            (r#"(string->number "+-1")"#, "false"),
+            (r#"(string->number "-2.5e-1")"#, "-0.25"),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+    }
+
+    #[test]
+    fn number_to_string() {
+        let examples = [
+            ("(number->string 42)", "42"),
+            ("(number->string -255 16)", "-ff"),
+            ("(number->string 10 2)", "1010"),
+            ("(number->string 1.0)", "1.0"),
+            ("(number->string 3.14159 10 2)", "3.14"),
+            ("(number->string 5 10 2)", "5.00"),
+            ("(number->string -5 10 0)", "-5"),
+            ("(number->string -inf.0)", "-inf.0"),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+
+        let ans = run("(number->string 1.5 16)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::InvalidRadix(16), _)
+        ));
+        let ans = run("(number->string 5 2 2)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::InvalidRadix(2), _)
+        ));
+    }
+
+    #[test]
+    fn string_literals() {
+        let examples = [
+            (r#""\x41;\u{3bb}\u{1F600}""#, "A\u{3bb}\u{1F600}"),
+            (r#""\a\0\b""#, "\u{7}\0\u{8}"),
+            ("\"tab\\tand\\nnewline\"", "tab\tand\nnewline"),
+            ("\"one \\\n       two\"", "one two"),
+            ("\"one \\   \n\t two\"", "one two"),
+            ("\"one \\\r\n  two\"", "one two"),
+            ("\"one \\  \r\n  two\"", "one two"),
+            ("#r\"C:\\path\\[a-z]+\\d\"", "C:\\path\\[a-z]+\\d"),
+            ("#r#\"say \"hi\"\nnext line\"#", "say \"hi\"\nnext line"),
+            ("#r\"\"", ""),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+
+        for src in [
+            r#""\q""#,
+            r#""\u41""#,
+            r#""\x110000;""#,
+            r#""\x41""#,
+            r#""\u{41""#,
+            "\"one \\\r two\"",
+            "\"\\ x\"",
+            "#r\"open",
+        ] {
+            assert!(matches!(run(src), Err(Error::Lexing(_))), "{}", src);
+        }
+
+        match run("\"one \\\r two\"") {
+            Err(Error::Lexing(les)) => {
+                assert_eq!(les[0].0.to_string(), "Invalid escape character \\r")
+            }
+            _ => panic!("lexing error expected"),
+        }
+    }
+
     #[test]
     fn string_eq() {
-        let src = r#"(list (= "a" "b") (= "a" (string-ref "cba" 2)) (= "a"))"#;
+        let src = r#"(list (= "a" "b") (= "a" (->string (string-ref "cba" 2))) (= "a"))"#;
         let ans = run(src).expect("Ok value expected").0;
         match ans {
             value::Value::List(list) => {
@@ -301,6 +670,286 @@ mod test {
         }
     }
 
+    #[test]
+    fn characters() {
+        let examples = [
+            (r"(char->integer #\A)", "65"),
+            (r"(integer->char 955)", "λ"),
+            (r"(list #\x41 #\space #\( #\λ)", "(A   ( λ)"),
+            (r"(char-upcase #\ß)", "ß"),
+            (r"(char-upcase #\a)", "A"),
+            (
+                r"(list (char-alphabetic? #\a) (char-numeric? #\a) (char-whitespace? #\tab))",
+                "(true false true)",
+            ),
+            (r"(list (digit-value #\7) (digit-value #\x))", "(7 false)"),
+            (
+                r"(list (char-numeric? #\½) (digit-value #\½) (char-numeric? #\9))",
+                "(false false true)",
+            ),
+            (r#"(string-ref "abc" 1)"#, "b"),
+            (r#"(char? (string-ref "abc" 1))"#, "true"),
+            (r#"(string->list "abc")"#, "(a b c)"),
+            (
+                r#"(list->string (map char-upcase (string->list "abc")))"#,
+                "ABC",
+            ),
+            (
+                r"(list (char<? #\a #\b #\c) (char-ci=? #\a #\A))",
+                "(true true)",
+            ),
+            (r"(compare #\b #\a)", "1"),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+
+        match run(r"#\bogus") {
+            Err(Error::Lexing(les)) => assert_eq!(
+                les[0].0,
+                tokenizer::LexingError::InvalidCharacter("bogus".to_string())
+            ),
+            _ => panic!("lexing error expected"),
+        }
+    }
+
+    #[test]
+    fn vectors() {
+        let examples = [
+            ("#(1 2.5 #\\a (1 2) #(3))", "#(1 2.5 a (1 2) #(3))"),
+            ("(vector-ref #(1 2 3) 1)", "2"),
+            ("(vector-length (make-vector 3 0))", "3"),
+            ("(make-vector 2 #t)", "#(true true)"),
+            ("(vector-copy #(1 2 3 4) 1 3)", "#(2 3)"),
+            ("(vector-map + #(1 2 3) #(10 20))", "#(11 22)"),
+            ("(vector->list #(1 2 3) 1)", "(2 3)"),
+            ("(list->vector (list 1 2))", "#(1 2)"),
+            ("(length (vector 1 2 3))", "3"),
+            (
+                "(begin (define v (make-vector 3 0)) (vector-set! v 1 5) v)",
+                "#(0 5 0)",
+            ),
+            (
+                "(begin (define v (vector 1 2 3 4)) (vector-fill! v 0 2) v)",
+                "#(1 2 0 0)",
+            ),
+            (
+                "(begin
+                    (define v (vector 1 2 3))
+                    (define alias v)
+                    (define sum 0)
+                    (vector-for-each (lambda (x) (set! sum (+ sum x))) v)
+                    (vector-set! alias 0 sum)
+                    v)",
+                "#(6 2 3)",
+            ),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+
+        for src in ["(vector-ref #(1 2 3) 3)", "(vector-ref #(1 2 3) -1)"] {
+            let ans = run(src).expect_err("Err value expected");
+            assert!(matches!(
+                ans,
+                Error::Runtime(eval::RuntimeError::IndexOutOfRange(_), _)
+            ));
+        }
+    }
+
+    #[test]
+    fn hash_tables() {
+        let src = r#"
+            (begin
+                (define counts (make-hash-table))
+                (define (count! key)
+                    (hash-table-update!/default counts key (lambda (n) (+ n 1)) 0))
+                (map count! (list 'a "a" 1 1.0 #\a (list 1 2) 'a (list 1 2) 1))
+                (hash-table-set! counts #(1 2) 'vector)
+                (hash-table-delete! counts 1.0)
+                (list
+                    (hash-table-ref counts 'a)
+                    (hash-table-ref counts "a")
+                    (hash-table-ref counts 1)
+                    (hash-table-ref counts #\a)
+                    (hash-table-ref counts (list 1 2))
+                    (hash-table-ref counts (vector 1 2))
+                    (hash-table-ref/default counts 1.0 'deleted)
+                    (hash-table-ref counts 'b (lambda () 'missing))
+                    (hash-table-contains? counts 'a)
+                    (hash-table-count counts)))"#;
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(ans.to_string(), "(2 1 2 1 2 vector deleted missing true 6)");
+
+        let src = "
+            (begin
+                (define squares (make-hash-table))
+                (map (lambda (i) (hash-table-set! squares i (* i i))) (iota 4))
+                (define sum 0)
+                (hash-table-walk squares (lambda (k v) (set! sum (+ sum k v))))
+                (list sum (length (hash-table-keys squares)) (length (hash-table->alist squares))))";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(ans.to_string(), "(20 4 4)");
+
+        let src = "
+            (begin
+                (define v (vector 1 'a))
+                (vector-set! v 0 v)
+                (define h (make-hash-table))
+                (hash-table-set! h v 'cyclic)
+                (hash-table-set! h (vector 1 'a) 'plain)
+                (list (hash-table-ref h v) (hash-table-ref h (vector 1 'a)) (hash-table-count h)))";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(ans.to_string(), "(cyclic plain 2)");
+
+        let ans = run("(hash-table-ref (make-hash-table) 'x)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::KeyNotFound(value::Value::Symbol(_)), _)
+        ));
+    }
+
+    #[test]
+    fn sets() {
+        let src = "
+            (begin
+                (define visited (set))
+                (set-add! visited (list 0 0) (list 0 1))
+                (set-add! visited (list 0 0))
+                (define a (set 1 2 3 4))
+                (define b (set 3 4 5))
+                (list
+                    (set-size visited)
+                    (set-contains? visited (list 0 1))
+                    (set-contains? visited (list 1 0))
+                    (set-size (set-union a b (set 6)))
+                    (set->list (set-intersection a b (set 4)))
+                    (set-size (set-difference a b))
+                    (set-size a)))";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(ans.to_string(), "(2 true false 6 (4) 2 4)");
+
+        let ans = run("(set 3 1 2 1)").expect("Ok value expected").0;
+        assert_eq!(ans.to_string(), "#<set 1 2 3>");
+        let ans = run("(set \"b\" #\\a)").expect("Ok value expected").0;
+        assert_eq!(
+            ans.written(value::Sharing::Cycles).to_string(),
+            "#<set \"b\" #\\a>"
+        );
+    }
+
+    #[test]
+    fn records() {
+        let src = "
+            (begin
+                (define-record-type <point>
+                    (make-point x y)
+                    point?
+                    (x point-x set-point-x!)
+                    (y point-y)
+                    (label point-label))
+                (define p (make-point 1 2))
+                (set-point-x! p 10)
+                (list p (point-x p) (point-y p) (point? p) (point? (list 1 2)) <point>))";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(
+            ans.to_string(),
+            "(#<point x: 10 y: 2 label: #<unspecified>> 10 2 true false <record type point>)"
+        );
+
+        let src = "
+            (begin
+                (define-record-type node (make-node value) node? (value node-value))
+                (define-record-type leaf (make-leaf value) leaf? (value leaf-value))
+                (node-value (make-leaf 1)))";
+        let ans = run(src).expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::RecordExpected(_, _), _)
+        ));
+    }
+
+    #[test]
+    fn equality_predicates() {
+        let src = r#"
+            (begin
+                (define s "abc")
+                (define v (vector 1 2))
+                (define-record-type point (make-point x y) point? (x point-x) (y point-y))
+                (define p (make-point 1 (list 2 3)))
+                (list
+                    (eq? 'a 'a)
+                    (eq? s s)
+                    (eq? "abc" (list->string (string->list s)))
+                    (eqv? 1 1)
+                    (eqv? 1 1.0)
+                    (eqv? v v)
+                    (eqv? v (vector 1 2))
+                    (eq? (list 1 2) (list 1 2))
+                    (eq? (list v) (list (vector 1 2)))
+                    (eqv? p p)
+                    (eqv? p (make-point 1 (list 2 3)))
+                    (equal? "abc" (list->string (string->list s)))
+                    (equal? (list 1 (vector 2 "x")) (list 1 (vector 2 "x")))
+                    (equal? p (make-point 1 (list 2 3)))
+                    (equal? 2 2.0)
+                    (equal? + +)))"#;
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(
+            ans.to_string(),
+            "(true true false true false true false true false true false true true true false true)"
+        );
+
+        let src = "
+            (begin
+                (define-record-type point (make-point x) point? (x point-x set-point-x!))
+                (define p (make-point 1))
+                (set-point-x! p p)
+                (define q (make-point 1))
+                (set-point-x! q q)
+                (define v (vector 1 2))
+                (vector-set! v 1 v)
+                (define w (vector 1 2))
+                (vector-set! w 1 w)
+                (define seen (set p v))
+                (list
+                    p
+                    (equal? p q)
+                    (equal? v w)
+                    (equal? v (vector 1 w))
+                    (equal? v (vector 2 w))
+                    (set-contains? seen q)
+                    (set-contains? seen w)))";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(
+            ans.to_string(),
+            "(#0=#<point x: #0#> true true true false true true)"
+        );
+    }
+
+    #[test]
+    fn numeric_equality() {
+        let examples = [
+            ("(= 1 1.0)", true),
+            ("(= 1 1.0 1)", true),
+            ("(= 1 1.5)", false),
+            ("(= 0.5 0.5)", true),
+            ("(= +nan.0 +nan.0)", false),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            match ans {
+                value::Value::Bool(ans) => assert_eq!(ans, expected, "{}", src),
+                _ => panic!(),
+            }
+        }
+    }
+
     fn values_to_ints(params: Vec<value::Value>) -> Vec<i64> {
         params
             .iter()
@@ -398,6 +1047,45 @@ mod test {
         assert!(matches!(ans, Ok((value::Value::Bool(true), _))));
     }
 
+    #[test]
+    fn empty_list_false_and_unspecified() {
+        let examples = [
+            ("(null? '())", "true"),
+            ("(null? (list))", "true"),
+            ("(null? #f)", "false"),
+            ("(null? (list 1))", "false"),
+            ("(if '() 'true 'false)", "true"),
+            ("(if 0 'true 'false)", "true"),
+            ("(if (define x 1) 'true 'false)", "true"),
+            ("(not '())", "false"),
+            ("(not #f)", "true"),
+            ("(and 1 2)", "2"),
+            ("(and 1 #f 2)", "false"),
+            ("(and)", "true"),
+            ("(and '() 'x)", "x"),
+            ("(or #f 1 2)", "1"),
+            ("(or #f '())", "()"),
+            ("(or #f #f)", "false"),
+            ("(or)", "false"),
+            ("(list (if #f #f))", "(#<unspecified>)"),
+            (
+                "(list (eof-object? (eof-object)) (eof-object? '()))",
+                "(true false)",
+            ),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{}", src);
+        }
+
+        let ans = run(r#"(string-ref "abc" 3)"#).expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::IndexOutOfRange(3), _)
+        ));
+    }
+
     #[test]
     fn int_comparators() {
         let examples = [
@@ -418,6 +1106,70 @@ mod test {
         }
     }
 
+    #[test]
+    fn mixed_number_comparators() {
+        let examples = [
+            ("(< 1.5 2.0)", true),
+            ("(< 1 1.5 2)", true),
+            ("(>= 2.0 2 1.5)", true),
+            ("(> 1 1.0)", false),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            match ans {
+                value::Value::Bool(ans) => assert_eq!(ans, expected, "{}", src),
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn string_comparators() {
+        let examples = [
+            (r#"(string<? "abc" "abd" "b")"#, true),
+            (r#"(string<=? "abc" "abc")"#, true),
+            (r#"(string>? "abc" "ABC")"#, true),
+            (r#"(string-ci=? "abc" "ABC" "aBc")"#, true),
+            (r#"(string-ci<? "abc" "ABD")"#, true),
+            (r#"(string=? "abc" "abcd")"#, false),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            match ans {
+                value::Value::Bool(ans) => assert_eq!(ans, expected, "{}", src),
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn generic_compare() {
+        let examples = [
+            ("(compare 1 2)", -1),
+            ("(compare 2.5 2)", 1),
+            (r#"(compare "b" "a")"#, 1),
+            ("(compare (list 1 2) (list 1 2))", 0),
+            ("(compare (list 1 2) (list 1 2 0))", -1),
+            ("(compare #f #t)", -1),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            match ans {
+                value::Value::Int(ans) => assert_eq!(ans, expected, "{}", src),
+                _ => panic!(),
+            }
+        }
+
+        let ans = run(r#"(compare 1 "a")"#).expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::IncomparableValues(_, _), _)
+        ));
+    }
+
     #[test]
     fn do_loop() {
         let src = "
@@ -440,4 +1192,427 @@ mod test {
         println!("{:?}", ans);
         assert!(matches!(ans, value::Value::Int(42)));
     }
+
+    #[test]
+    fn error_locations() {
+        let src = "(define (f x)\n  (+ x \"a\"))\n(f 1)";
+        match run(src) {
+            Err(Error::Runtime(eval::RuntimeError::NumberExpected(_), trace)) => {
+                let location = trace.location.expect("location expected");
+                assert_eq!((location.line, location.column), (2, 3));
+                assert_eq!(location.span, 16..25);
+            }
+            _ => panic!("runtime error expected"),
+        }
+        let ans = run(src).expect_err("Err value expected");
+        assert!(ans.to_string().starts_with(
+            "+: expected a number, got \"a\"\n --> <test>:2:3\n  |\n2 |   (+ x \"a\"))\n  |   ^^^^^^^^^\n"
+        ));
+
+        let ans = run("(+ 1 undefined)").expect_err("Err value expected");
+        assert!(ans.to_string().contains("<test>:1:6"), "{ans}");
+
+        match run("(+ 1 2))") {
+            Err(Error::Parsing(pes)) => match pes.as_slice() {
+                [parser::ParsingError::UnexpectedRightBracket(location)] => {
+                    assert_eq!(location.to_string(), "<test>:1:8")
+                }
+                _ => panic!("unexpected right bracket expected"),
+            },
+            _ => panic!("parsing error expected"),
+        }
+        match run("(+ 1\n  #\\bogus)") {
+            Err(Error::Lexing(les)) => assert_eq!(les[0].1.to_string(), "<test>:2:3"),
+            _ => panic!("lexing error expected"),
+        }
+    }
+
+    #[test]
+    fn backtraces() {
+        let src = "
+            (define (f x) (+ x \"a\"))
+            (define (g n) (if (= n 0) (f n) (g (- n 1))))
+            (g 20)";
+        let (error, trace) = match run(src) {
+            Err(Error::Runtime(error, trace)) => (error, trace),
+            _ => panic!("runtime error expected"),
+        };
+        assert!(matches!(error, eval::RuntimeError::NumberExpected(_)));
+        // +, f and 21 calls of g
+        assert_eq!(trace.backtrace.len(), 23);
+        assert_eq!(trace.backtrace[1].arguments, "x = 0");
+        assert_eq!(trace.backtrace[22].arguments, "n = 20");
+        let call_sites: Vec<_> = trace
+            .backtrace
+            .iter()
+            .map(|frame| frame.call_site.as_ref().expect("call site expected").line)
+            .collect();
+        assert_eq!(call_sites[..3], [2, 3, 3]);
+        assert_eq!(call_sites[22], 4);
+
+        let error = Error::Runtime(error, trace);
+        let short = error.to_string();
+        assert!(short.contains("... 13 more frames"), "{short}");
+        let full = format!("{error:#}");
+        assert!(!full.contains("more frames"), "{full}");
+        assert!(full.contains("  22: g with n = 20"), "{full}");
+
+        let src = "
+            (define v (vector 1))
+            (vector-set! v 0 v)
+            (define (f xs v) (+ 1 \"a\"))
+            (f (iota 1000) v)";
+        let trace = match run(src) {
+            Err(Error::Runtime(_, trace)) => trace,
+            _ => panic!("runtime error expected"),
+        };
+        assert_eq!(
+            trace.backtrace[1].arguments,
+            "xs = (0 1 2 3 4 5 6 7 8 9 10 11 12 ..., v = #0=#(#0#)"
+        );
+    }
+
+    #[test]
+    fn error_messages() {
+        let examples = [
+            ("(+ 1 \"a\")", "expected a number, got \"a\""),
+            ("(char-upcase 1)", "expected a character, got 1"),
+            ("(vector-ref (list 1) 0)", "expected a vector, got (1)"),
+            ("(undefined 1)", "unbound variable `undefined`"),
+            ("(1 2)", "operator is not a procedure"),
+            ("(apply 1 (list))", "1 is not a procedure"),
+            ("(if)", "ill-formed special form `if`"),
+            (
+                "(define (f x) x) (f 1 2)",
+                "wrong number of arguments: expected 1, got 2",
+            ),
+            (
+                "(vector-fill! (vector))",
+                "wrong number of arguments: expected 2 to 4, got 1",
+            ),
+            (
+                "(map not)",
+                "wrong number of arguments: expected at least 2, got 1",
+            ),
+            ("(/ 1 0)", "division by zero"),
+            ("(integer->char -1)", "-1 is not a valid Unicode code point"),
+            ("(string->number \"1\" 99)", "invalid radix 99"),
+            ("(compare 1 #\\a)", "cannot compare 1 with #\\a"),
+            (
+                "(read-file \"/does/not/exist\")",
+                "cannot read `/does/not/exist`: entity not found",
+            ),
+        ];
+
+        for (src, expected) in examples {
+            match run(src) {
+                Err(Error::Runtime(e, _)) => assert_eq!(e.to_string(), expected, "{src}"),
+                _ => panic!("runtime error expected for {src}"),
+            }
+        }
+    }
+
+    #[test]
+    fn all_errors_reported() {
+        match run("(list #\\bogus 1_ #\\nope)") {
+            Err(Error::Lexing(les)) => {
+                let columns: Vec<_> = les.iter().map(|(_, location)| location.column).collect();
+                assert_eq!(columns, [7, 15, 18]);
+            }
+            _ => panic!("lexing errors expected"),
+        }
+
+        let src = "(define (f x)\n  (+ x 1)))\n(list (1 2)\n(g 3 '";
+        let pes = match run(src) {
+            Err(Error::Parsing(pes)) => pes,
+            _ => panic!("parsing errors expected"),
+        };
+        let errors: Vec<_> = pes
+            .iter()
+            .map(|e| {
+                let kind = match e {
+                    parser::ParsingError::ExpectedToken(_) => "expected",
+                    parser::ParsingError::UnexpectedRightBracket(_) => "stray",
+                    parser::ParsingError::UnclosedBracket(_) => "unclosed",
+                };
+                (kind, e.location().to_string())
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("stray", "<test>:2:11".to_string()),
+                ("unclosed", "<test>:3:1".to_string()),
+                ("unclosed", "<test>:4:1".to_string()),
+                ("expected", "<test>:4:7".to_string()),
+            ]
+        );
+
+        // `read` reports the first error of the datum, the outer bracket is closed last
+        let ans = run("(read \"(a (b\")").expect_err("Err value expected");
+        assert!(
+            matches!(&ans, Error::Runtime(eval::RuntimeError::InvalidSyntax(message), _)
+                if message == "Unclosed bracket at <read>:1:1"),
+            "{ans}"
+        );
+    }
+
+    #[test]
+    fn named_procedures() {
+        let examples = [
+            ("(define (f x) x) f", "<procedure f with 1 parameters>"),
+            (
+                "(define f (lambda (x y) x)) f",
+                "<procedure f with 2 parameters>",
+            ),
+            (
+                "(define (f x) x) (define g f) g",
+                "<procedure f with 1 parameters>",
+            ),
+            ("(lambda () 1)", "<procedure with 0 parameters>"),
+            ("length", "<built-in function length>"),
+            ("(define plus +) plus", "<built-in function +>"),
+            (
+                "make-generator",
+                "<procedure make-generator with 2 parameters>",
+            ),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{src}");
+        }
+
+        let src = "(define f (lambda (x) (length x))) (f 1)";
+        match run(src) {
+            Err(Error::Runtime(_, trace)) => {
+                let frames: Vec<_> = trace.backtrace.iter().map(|f| &f.procedure).collect();
+                assert_eq!(frames, ["length", "f"]);
+            }
+            _ => panic!("runtime error expected"),
+        }
+    }
+
+    #[test]
+    fn procedure_introspection() {
+        let examples = [
+            ("(procedure? length)", "true"),
+            ("(procedure? (lambda (x) x))", "true"),
+            ("(procedure? 'length)", "false"),
+            ("(procedure? if)", "false"),
+            ("(procedure-arity (lambda (x y) x))", "2"),
+            ("(procedure-arity length)", "false"),
+            ("(define (f x) x) (procedure-name f)", "f"),
+            ("(procedure-name length)", "length"),
+            ("(procedure-name (lambda () 1))", "false"),
+            (
+                "(define (f x) (* x  2)) (procedure-source f)",
+                "(lambda (x) (* x 2))",
+            ),
+            ("(procedure-source length)", "false"),
+            (
+                "(procedure-environment (make-generator 1 2))",
+                "((start 1) (step 2) (val 1))",
+            ),
+            ("(define (f x) x) (procedure-environment f)", "()"),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{src}");
+        }
+
+        let src = "
+            (define (count-down from)
+              (if (> from 0) (begin (display from) (count-down (- from 1))) (display \"lift off\")))
+            (procedure-source count-down)";
+        let ans = run(src).expect("Ok value expected").0;
+        assert_eq!(
+            ans.to_string(),
+            "(lambda (from)\n  (if (> from 0)\n    (begin (display from) (count-down (- from 1)))\n    (display \"lift off\")))"
+        );
+
+        let ans = run("(procedure-name 1)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::NotProcedure(value::Value::Int(1)), _)
+        ));
+    }
+
+    #[test]
+    fn environments() {
+        let examples = [
+            (
+                "(define (f x) (the-environment)) (environment-ref (f 42) 'x)",
+                "42",
+            ),
+            ("(defined? 'length)", "true"),
+            ("(defined? 'undefined)", "false"),
+            ("(define x 1) (defined? 'x)", "true"),
+            ("(define (f y) (defined? 'y)) (f 1)", "true"),
+            (
+                "(define (f y) (the-environment)) (defined? 'y (f 1))",
+                "true",
+            ),
+            ("(defined? 'y (interaction-environment))", "false"),
+            (
+                "(define x 1) (environment-ref (interaction-environment) 'x)",
+                "1",
+            ),
+            (
+                "(define x 1) (environment-assign! (interaction-environment) 'x 2) x",
+                "2",
+            ),
+            ("(define (f) (the-environment)) (equal? (f) (f))", "false"),
+            (
+                "(equal? (the-environment) (interaction-environment))",
+                "true",
+            ),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{src}");
+        }
+
+        let src = "(define (f x) (the-environment)) (environment-bound-names (f 1))";
+        let names = match run(src).expect("Ok value expected").0 {
+            value::Value::List(names) => names.iter().map(|n| n.to_string()).collect::<Vec<_>>(),
+            _ => panic!("list expected"),
+        };
+        assert!(names.is_sorted());
+        for name in ["x", "f", "length", "make-generator"] {
+            assert!(names.iter().any(|n| n == name), "{name}");
+        }
+
+        let ans = run("(environment-ref (interaction-environment) 'undefined)")
+            .expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::UnboundVariable(_), _)
+        ));
+        let ans = run("(environment-ref 1 'x)").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::EnvironmentExpected(_), _)
+        ));
+    }
+
+    #[test]
+    fn read_and_eval() {
+        let examples = [
+            ("(read \"(+ 1 2)\")", "(+ 1 2)"),
+            ("(read \"  42 43\")", "42"),
+            ("(eof-object? (read \"  \"))", "true"),
+            ("(eof-object? (read \"#;1\"))", "true"),
+            ("(eof-object? (read \"#;1 ; comment\"))", "true"),
+            ("(read \"#;1 #;(2 3) 4\")", "4"),
+            ("(eval (read \"(+ 1 2)\") (the-environment))", "3"),
+            (
+                "(eval '(string=? \"a\\\"b\" \"a\\\"b\") (the-environment))",
+                "true",
+            ),
+            ("(eval (list 'quote #\\a) (the-environment))", "a"),
+            ("(define x 1) (eval 'x (interaction-environment))", "1"),
+            (
+                "(define (f x) (the-environment)) (eval '(* x 2) (f 21))",
+                "42",
+            ),
+            ("(eval '(define y 5) (interaction-environment)) y", "5"),
+            ("(eval #(1 2.0) (the-environment))", "#(1 2)"),
+            (
+                "(eval (list '+ 1.5 (list 'quote 2.5)) (the-environment))",
+                "4",
+            ),
+            (
+                "(eval (list 'quote '|odd symbol|) (the-environment))",
+                "odd symbol",
+            ),
+        ];
+
+        for (src, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.to_string(), expected, "{src}");
+        }
+
+        let ans = run("(read \"(1 2\")").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::InvalidSyntax(_), _)
+        ));
+        let src = "(define v (vector 1)) (vector-set! v 0 v) (eval v (the-environment))";
+        let ans = run(src).expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::DatumExpected(_), _)
+        ));
+        let ans = run("(define v (vector 1)) (eval (list 'list v v) (the-environment))")
+            .expect("Ok value expected")
+            .0;
+        assert_eq!(ans.to_string(), "(#(1) #(1))");
+        let ans = run("(eval (list length) (the-environment))").expect_err("Err value expected");
+        assert!(matches!(
+            ans,
+            Error::Runtime(eval::RuntimeError::DatumExpected(_), _)
+        ));
+        let ans = run("(eval 'undefined (the-environment))").expect_err("Err value expected");
+        assert!(report(&ans).contains("--> <eval>:1:1"), "{}", report(&ans));
+    }
+
+    #[test]
+    fn written_values() {
+        let examples = [
+            (
+                "(list \"a b\" 1.0 1)",
+                value::Sharing::Cycles,
+                "(\"a b\" 1.0 1)",
+            ),
+            ("\"a\\\"b\\n\"", value::Sharing::Cycles, "\"a\\\"b\\n\""),
+            (
+                "(list #\\a #\\space #t #f)",
+                value::Sharing::Cycles,
+                "(#\\a #\\space #t #f)",
+            ),
+            ("(list 'a '|b c|)", value::Sharing::Cycles, "(a |b c|)"),
+            (
+                "(vector 1 (vector 2.5))",
+                value::Sharing::Cycles,
+                "#(1 #(2.5))",
+            ),
+            (
+                "(define v (vector 1 2)) (vector-set! v 1 v) v",
+                value::Sharing::Cycles,
+                "#0=#(1 #0#)",
+            ),
+            (
+                "(define v (vector 1)) (list v v)",
+                value::Sharing::Cycles,
+                "(#(1) #(1))",
+            ),
+            (
+                "(define v (vector 1)) (list v v)",
+                value::Sharing::Always,
+                "(#0=#(1) #0#)",
+            ),
+            (
+                "(define v (vector 1)) (list v v)",
+                value::Sharing::Never,
+                "(#(1) #(1))",
+            ),
+        ];
+
+        for (src, sharing, expected) in examples {
+            let ans = run(src).expect("Ok value expected").0;
+            assert_eq!(ans.written(sharing).to_string(), expected, "{src}");
+        }
+
+        for src in ["(list \"a b\" 1.0 #\\a #t 'c)", "#(1 (2 \"\\t\"))"] {
+            let ans = run(src).expect("Ok value expected").0;
+            let written = ans.written(value::Sharing::Cycles).to_string();
+            let read = run(&format!("(read {})", tokenizer::escape_string(&written)))
+                .expect("Ok value expected")
+                .0;
+            assert_eq!(read, ans, "{src}");
+        }
+    }
 }
@@ -1,58 +1,272 @@
-use crate::tokenizer::Token;
+use crate::{
+    source::{Location, Source},
+    tokenizer::Token,
+};
 use logos::Span;
-use std::{fmt::Display, rc::Rc};
+use std::{fmt::Display, iter::Peekable, rc::Rc};
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
     Token(Token),
     List(Vec<Rc<Expr>>),
+    Vector(Vec<Rc<Expr>>),
+}
+
+/// Width of a line in pretty-printed source
+pub const LINE_WIDTH: usize = 80;
+
+impl Expr {
+    /// Formats the expression as source code. Lists that do not fit on the line starting at
+    /// `indent` are broken into one element per line, indented under the head.
+    pub fn pretty(&self, indent: usize) -> String {
+        let flat = self.to_string();
+        let (open, items) = match &self.kind {
+            ExprKind::List(items) => ("(", items),
+            ExprKind::Vector(items) => ("#(", items),
+            ExprKind::Token(_) => return flat,
+        };
+        let Some((head, tail)) = items.split_first() else {
+            return flat;
+        };
+        if indent + flat.chars().count() <= LINE_WIDTH {
+            return flat;
+        }
+
+        let inner = indent + 2;
+        let mut result = format!("{open}{}", head.pretty(indent + open.len()));
+        // the first operand of a procedure or special form stays next to the operator
+        let tail = match (&head.kind, tail) {
+            (ExprKind::Token(_), [first, rest @ ..]) => {
+                let column = indent + open.len() + head.to_string().chars().count() + 1;
+                result.push(' ');
+                result.push_str(&first.pretty(column));
+                rest
+            }
+            _ => tail,
+        };
+        for item in tail {
+            result.push('\n');
+            result.push_str(&" ".repeat(inner));
+            result.push_str(&item.pretty(inner));
+        }
+        result.push(')');
+        result
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (open, items) = match &self.kind {
+            ExprKind::Token(token) => return write!(f, "{token}"),
+            ExprKind::List(items) => ("(", items),
+            ExprKind::Vector(items) => ("#(", items),
+        };
+        write!(f, "{open}")?;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{item}")?;
+        }
+        write!(f, ")")
+    }
 }
 
 #[derive(Debug)]
 pub enum ParsingError {
-    ExpectedToken,
-    UnexpectedRightBracket(Span),
+    ExpectedToken(Location),
+    UnexpectedRightBracket(Location),
+    /// Bracket opened at the location is never closed
+    UnclosedBracket(Location),
+}
+
+impl ParsingError {
+    pub fn location(&self) -> &Location {
+        match self {
+            ParsingError::ExpectedToken(location)
+            | ParsingError::UnexpectedRightBracket(location)
+            | ParsingError::UnclosedBracket(location) => location,
+        }
+    }
+
+    /// Message without the location
+    pub fn description(&self) -> &'static str {
+        match self {
+            ParsingError::ExpectedToken(_) => "Expected token",
+            ParsingError::UnexpectedRightBracket(_) => "Unexpected right bracket",
+            ParsingError::UnclosedBracket(_) => "Unclosed bracket",
+        }
+    }
 }
 
 impl Display for ParsingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ParsingError::ExpectedToken => write!(f, "Expected token"),
-            ParsingError::UnexpectedRightBracket(s) => {
-                write!(f, "Unexpeted right bracket at {}", s.start)
+        self.location().fmt_message(f, &self.description())
+    }
+}
+
+/// Parser that keeps going after an error, so that all mistakes in the program are reported at
+/// once. Errors are collected in `errors` and the broken parts of the tree are left out.
+struct Parser<I: Iterator<Item = (Token, Span)>> {
+    tokens: Peekable<I>,
+    source: Rc<Source>,
+    errors: Vec<ParsingError>,
+}
+
+impl<I: Iterator<Item = (Token, Span)>> Parser<I> {
+    fn new(tokens: I, source: &Rc<Source>) -> Self {
+        Self {
+            tokens: tokens.peekable(),
+            source: source.clone(),
+            errors: vec![],
+        }
+    }
+
+    /// Parses the datum that has to follow a quote or a datum comment. A right bracket is left
+    /// in place, so that it still closes the enclosing list.
+    fn parse_following(&mut self) -> Option<Expr> {
+        match self.tokens.peek() {
+            None => {
+                self.errors
+                    .push(ParsingError::ExpectedToken(self.source.end()));
+                None
+            }
+            Some((Token::RBracket, span)) => {
+                let location = self.source.location(span.clone());
+                self.errors
+                    .push(ParsingError::UnexpectedRightBracket(location));
+                None
+            }
+            Some(_) => {
+                let head = self.tokens.next()?;
+                self.parse_datum(head)
             }
         }
     }
-}
 
-fn parse_recursive<I>(head: (Token, Span), tail: &mut I) -> Result<Expr, ParsingError>
-where
-    I: Iterator<Item = (Token, Span)>,
-{
-    match head {
-        (Token::LBracket, _) => {
-            let mut list = vec![];
-            loop {
-                let (head, span) = tail.next().ok_or(ParsingError::ExpectedToken)?;
-                match head {
-                    Token::RBracket => break,
-                    _ => {
-                        let expr = parse_recursive((head, span), tail)?;
+    /// Parses elements up to the closing bracket and returns them with the location of the
+    /// bracket. Returns `None` when the input ends before the list is closed.
+    fn parse_until_right_bracket(&mut self, open: &Location) -> Option<(Vec<Rc<Expr>>, Location)> {
+        let mut list = vec![];
+        loop {
+            let Some((head, span)) = self.tokens.next() else {
+                self.errors
+                    .push(ParsingError::UnclosedBracket(open.clone()));
+                return None;
+            };
+            match head {
+                Token::RBracket => return Some((list, self.source.location(span))),
+                Token::DatumComment => {
+                    self.parse_following();
+                }
+                _ => {
+                    if let Some(expr) = self.parse_datum((head, span)) {
                         list.push(Rc::new(expr));
                     }
                 }
             }
-            Ok(Expr::List(list))
         }
-        (Token::RBracket, span) => Err(ParsingError::UnexpectedRightBracket(span)),
-        (token, _) => Ok(Expr::Token(token)),
+    }
+
+    /// Parses the datum starting with the head token, which must not be a right bracket
+    fn parse_datum(&mut self, head: (Token, Span)) -> Option<Expr> {
+        let (token, span) = head;
+        let location = self.source.location(span);
+        match token {
+            Token::LBracket => {
+                let (list, end) = self.parse_until_right_bracket(&location)?;
+                Some(Expr {
+                    kind: ExprKind::List(list),
+                    location: location.to(&end),
+                })
+            }
+            Token::VectorStart => {
+                let (items, end) = self.parse_until_right_bracket(&location)?;
+                Some(Expr {
+                    kind: ExprKind::Vector(items),
+                    location: location.to(&end),
+                })
+            }
+            Token::QuoteMark => {
+                // 'datum is a shorthand for (quote datum)
+                let datum = self.parse_following()?;
+                let quote = Expr {
+                    kind: ExprKind::Token(Token::Symbol("quote".to_string())),
+                    location: location.clone(),
+                };
+                Some(Expr {
+                    location: location.to(&datum.location),
+                    kind: ExprKind::List(vec![Rc::new(quote), Rc::new(datum)]),
+                })
+            }
+            Token::DatumComment => {
+                // skip the commented datum and parse the one that follows it
+                self.parse_following();
+                self.parse_following()
+            }
+            Token::RBracket => {
+                self.errors
+                    .push(ParsingError::UnexpectedRightBracket(location));
+                None
+            }
+            token => Some(Expr {
+                kind: ExprKind::Token(token),
+                location,
+            }),
+        }
+    }
+
+    /// Returns the result unless there were errors, `None` comes with at least one error
+    fn finish<T>(mut self, result: Option<T>) -> Result<T, Vec<ParsingError>> {
+        self.errors.sort_by_key(|e| e.location().span.start);
+        match result {
+            Some(result) if self.errors.is_empty() => Ok(result),
+            _ => Err(self.errors),
+        }
     }
 }
 
-pub fn parse<I>(mut tokens: I) -> Result<Expr, ParsingError>
+/// Parses the first expression of the input, `None` when the input holds only datum comments
+pub fn parse<I>(tokens: I, source: &Rc<Source>) -> Result<Option<Expr>, Vec<ParsingError>>
 where
     I: Iterator<Item = (Token, Span)>,
 {
-    let head = tokens.next().ok_or(ParsingError::ExpectedToken)?;
-    parse_recursive(head, &mut tokens)
+    let mut parser = Parser::new(tokens, source);
+    while parser
+        .tokens
+        .next_if(|(token, _)| matches!(token, Token::DatumComment))
+        .is_some()
+    {
+        parser.parse_following();
+    }
+    if parser.tokens.peek().is_none() {
+        return parser.finish(Some(None));
+    }
+    let expr = parser.parse_following();
+    parser.finish(expr.map(Some))
+}
+
+/// Parses all top-level expressions of a program. A stray right bracket is reported and skipped,
+/// so it does not hide mistakes that follow it.
+pub fn parse_program<I>(tokens: I, source: &Rc<Source>) -> Result<Vec<Expr>, Vec<ParsingError>>
+where
+    I: Iterator<Item = (Token, Span)>,
+{
+    let mut parser = Parser::new(tokens, source);
+    let mut exprs = vec![];
+    while let Some((token, span)) = parser.tokens.next() {
+        match token {
+            Token::DatumComment => {
+                parser.parse_following();
+            }
+            token => exprs.extend(parser.parse_datum((token, span))),
+        }
+    }
+    parser.finish(Some(exprs))
 }
@@ -1,37 +1,103 @@
 use crate::{
     builtin,
     eval::RuntimeError,
-    parser, tokenizer,
-    value::{BuiltIn, Procedure, Value},
+    parser,
+    source::Source,
+    special_forms, tokenizer,
+    value::{BuiltIn, Procedure, SpecialForm, SyntaxTransformer, Value},
 };
-use std::{cell::RefCell, collections::HashMap, f64::consts::PI, rc::Rc};
+use itertools::Itertools;
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, f64::consts::PI, rc::Rc};
 
-#[derive(Debug)]
+/// Lexical environment. Special forms are bound in the same namespace as variables, so they can be
+/// shadowed or renamed like any other binding.
 pub struct Scope {
     frame: Rc<RefCell<HashMap<String, Value>>>,
     outer: Option<Rc<Scope>>,
 }
 
+// values are left out, procedures and environments bound in a scope usually refer back to it
+impl std::fmt::Debug for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scope")
+            .field(
+                "variables",
+                &self.variables().into_iter().sorted().collect_vec(),
+            )
+            .field("outer", &self.outer)
+            .finish()
+    }
+}
+
 fn add_procedure(name: &str, param_names: Vec<String>, src: &str, scope: &Rc<Scope>) {
     // This is internal method only for adding built-in procedures that expects
     // correct syntax. Therefore we can unwrap results of both tokenization and parsing.
+    let source = Source::new(&format!("<built-in {name}>"), src);
     let tokens = tokenizer::tokenize(src).unwrap();
-    let body = Rc::new(parser::parse(tokens.into_iter()).unwrap());
-    let procedure = Value::Procedure(Procedure::new(param_names, vec![body], scope.clone()));
+    let body = Rc::new(parser::parse(tokens.into_iter(), &source).unwrap().unwrap());
+    let procedure = Procedure::new(Some(name), param_names, vec![body], scope.clone());
+    let procedure = Value::Procedure(Rc::new(procedure));
     Scope::define(scope, name, procedure);
 }
 
 fn add_built_in(
     frame: &mut HashMap<String, Value>,
-    symbol: &str,
+    symbol: &'static str,
     func: fn(Vec<Value>) -> Result<Value, RuntimeError>,
 ) {
-    frame.insert(symbol.to_string(), Value::BuiltIn(BuiltIn::new(func)));
+    frame.insert(
+        symbol.to_string(),
+        Value::BuiltIn(BuiltIn::new(symbol, func)),
+    );
+}
+
+fn add_special_form(
+    frame: &mut HashMap<String, Value>,
+    symbol: &'static str,
+    func: SyntaxTransformer,
+) {
+    frame.insert(
+        symbol.to_string(),
+        Value::SpecialForm(SpecialForm::new(symbol, func)),
+    );
 }
 
 impl Scope {
     pub fn global() -> Rc<Self> {
         let mut frame = HashMap::new();
+        // list of commands - evaluate all and return last one
+        add_special_form(&mut frame, "begin", special_forms::begin);
+        // create new variable
+        add_special_form(&mut frame, "define", special_forms::define);
+        // loop
+        add_special_form(&mut frame, "do", special_forms::do_loop);
+        // set value of variable
+        add_special_form(&mut frame, "set!", special_forms::set);
+        // create custom procedure
+        add_special_form(&mut frame, "lambda", special_forms::lambda);
+        // if (cond) (if_true_expr) (else_expr)
+        add_special_form(&mut frame, "if", special_forms::if_statement);
+        // literal data
+        add_special_form(&mut frame, "quote", special_forms::quote);
+        // first-class environments
+        add_special_form(
+            &mut frame,
+            "the-environment",
+            special_forms::the_environment,
+        );
+        add_special_form(
+            &mut frame,
+            "interaction-environment",
+            special_forms::interaction_environment,
+        );
+        add_special_form(&mut frame, "defined?", special_forms::is_defined);
+        // record type with its constructor, predicate, accessors and modifiers
+        add_special_form(
+            &mut frame,
+            "define-record-type",
+            special_forms::define_record_type,
+        );
+
         add_built_in(&mut frame, "+", builtin::op_add);
         add_built_in(&mut frame, "-", builtin::op_sub);
         add_built_in(&mut frame, "*", builtin::op_mul);
@@ -39,36 +105,188 @@ impl Scope {
         add_built_in(&mut frame, "and", builtin::and);
         add_built_in(&mut frame, "or", builtin::or);
         add_built_in(&mut frame, "not", builtin::not);
+        add_built_in(&mut frame, "null?", builtin::is_null);
+        add_built_in(&mut frame, "eof-object", builtin::eof_object);
+        add_built_in(&mut frame, "eof-object?", builtin::is_eof_object);
         add_built_in(&mut frame, "mod", builtin::modulo);
         add_built_in(&mut frame, "=", builtin::op_eq);
+        // lists are immutable values and everything else is compared by identity or value,
+        // so there is no observable difference between eq? and eqv?
+        add_built_in(&mut frame, "eq?", builtin::is_eqv);
+        add_built_in(&mut frame, "eqv?", builtin::is_eqv);
+        add_built_in(&mut frame, "equal?", builtin::is_equal);
+        add_built_in(&mut frame, "bitwise-and", builtin::bitwise_and);
+        add_built_in(&mut frame, "bitwise-or", builtin::bitwise_or);
+        add_built_in(&mut frame, "bitwise-xor", builtin::bitwise_xor);
+        add_built_in(&mut frame, "bitwise-not", builtin::bitwise_not);
+        add_built_in(&mut frame, "arithmetic-shift", builtin::arithmetic_shift);
+        add_built_in(&mut frame, "bit-count", builtin::bit_count);
+        add_built_in(&mut frame, "bit-set?", builtin::bit_set);
+        add_built_in(&mut frame, "first-set-bit", builtin::first_set_bit);
+        add_built_in(&mut frame, "integer-length", builtin::integer_length);
 
         add_built_in(&mut frame, "<=", |params| {
-            builtin::pairwise_compare(&params, |(a, b)| a <= b)
+            builtin::pairwise_compare(&params, Ordering::is_le)
         });
         add_built_in(&mut frame, "<", |params| {
-            builtin::pairwise_compare(&params, |(a, b)| a < b)
+            builtin::pairwise_compare(&params, Ordering::is_lt)
         });
         add_built_in(&mut frame, ">=", |params| {
-            builtin::pairwise_compare(&params, |(a, b)| a >= b)
+            builtin::pairwise_compare(&params, Ordering::is_ge)
         });
         add_built_in(&mut frame, ">", |params| {
-            builtin::pairwise_compare(&params, |(a, b)| a > b)
+            builtin::pairwise_compare(&params, Ordering::is_gt)
+        });
+        add_built_in(&mut frame, "compare", builtin::compare);
+
+        add_built_in(&mut frame, "string=?", |params| {
+            builtin::string_compare(&params, Ordering::is_eq, false)
+        });
+        add_built_in(&mut frame, "string<?", |params| {
+            builtin::string_compare(&params, Ordering::is_lt, false)
+        });
+        add_built_in(&mut frame, "string<=?", |params| {
+            builtin::string_compare(&params, Ordering::is_le, false)
+        });
+        add_built_in(&mut frame, "string>?", |params| {
+            builtin::string_compare(&params, Ordering::is_gt, false)
+        });
+        add_built_in(&mut frame, "string>=?", |params| {
+            builtin::string_compare(&params, Ordering::is_ge, false)
+        });
+        add_built_in(&mut frame, "string-ci=?", |params| {
+            builtin::string_compare(&params, Ordering::is_eq, true)
+        });
+        add_built_in(&mut frame, "string-ci<?", |params| {
+            builtin::string_compare(&params, Ordering::is_lt, true)
+        });
+        add_built_in(&mut frame, "string-ci<=?", |params| {
+            builtin::string_compare(&params, Ordering::is_le, true)
+        });
+        add_built_in(&mut frame, "string-ci>?", |params| {
+            builtin::string_compare(&params, Ordering::is_gt, true)
+        });
+        add_built_in(&mut frame, "string-ci>=?", |params| {
+            builtin::string_compare(&params, Ordering::is_ge, true)
+        });
+
+        add_built_in(&mut frame, "char=?", |params| {
+            builtin::char_compare(&params, Ordering::is_eq, false)
+        });
+        add_built_in(&mut frame, "char<?", |params| {
+            builtin::char_compare(&params, Ordering::is_lt, false)
+        });
+        add_built_in(&mut frame, "char<=?", |params| {
+            builtin::char_compare(&params, Ordering::is_le, false)
+        });
+        add_built_in(&mut frame, "char>?", |params| {
+            builtin::char_compare(&params, Ordering::is_gt, false)
+        });
+        add_built_in(&mut frame, "char>=?", |params| {
+            builtin::char_compare(&params, Ordering::is_ge, false)
+        });
+        add_built_in(&mut frame, "char-ci=?", |params| {
+            builtin::char_compare(&params, Ordering::is_eq, true)
         });
 
         add_built_in(&mut frame, "list", builtin::list);
         add_built_in(&mut frame, "iota", builtin::iota);
         add_built_in(&mut frame, "zip", builtin::zip);
         add_built_in(&mut frame, "append", builtin::append);
+        add_built_in(&mut frame, "vector", builtin::vector);
+        add_built_in(&mut frame, "make-vector", builtin::make_vector);
+        add_built_in(&mut frame, "vector-ref", builtin::vector_ref);
+        add_built_in(&mut frame, "vector-set!", builtin::vector_set);
+        add_built_in(&mut frame, "vector-length", builtin::vector_length);
+        add_built_in(&mut frame, "vector-fill!", builtin::vector_fill);
+        add_built_in(&mut frame, "vector-copy", builtin::vector_copy);
+        add_built_in(&mut frame, "vector-map", builtin::vector_map);
+        add_built_in(&mut frame, "vector-for-each", builtin::vector_for_each);
+        add_built_in(&mut frame, "vector->list", builtin::vector_to_list);
+        add_built_in(&mut frame, "list->vector", builtin::list_to_vector);
+        add_built_in(&mut frame, "make-hash-table", builtin::make_hash_table);
+        add_built_in(&mut frame, "hash-table-ref", builtin::hash_table_ref);
+        add_built_in(
+            &mut frame,
+            "hash-table-ref/default",
+            builtin::hash_table_ref_default,
+        );
+        add_built_in(&mut frame, "hash-table-set!", builtin::hash_table_set);
+        add_built_in(
+            &mut frame,
+            "hash-table-update!/default",
+            builtin::hash_table_update_default,
+        );
+        add_built_in(&mut frame, "hash-table-delete!", builtin::hash_table_delete);
+        add_built_in(
+            &mut frame,
+            "hash-table-contains?",
+            builtin::hash_table_contains,
+        );
+        add_built_in(&mut frame, "hash-table-count", builtin::hash_table_count);
+        add_built_in(&mut frame, "hash-table-keys", builtin::hash_table_keys);
+        add_built_in(&mut frame, "hash-table-values", builtin::hash_table_values);
+        add_built_in(
+            &mut frame,
+            "hash-table->alist",
+            builtin::hash_table_to_alist,
+        );
+        add_built_in(&mut frame, "hash-table-walk", builtin::hash_table_walk);
+        add_built_in(&mut frame, "set", builtin::set);
+        add_built_in(&mut frame, "set-add!", builtin::set_add);
+        add_built_in(&mut frame, "set-contains?", builtin::set_contains);
+        add_built_in(&mut frame, "set-union", builtin::set_union);
+        add_built_in(&mut frame, "set-intersection", builtin::set_intersection);
+        add_built_in(&mut frame, "set-difference", builtin::set_difference);
+        add_built_in(&mut frame, "set->list", builtin::set_to_list);
+        add_built_in(&mut frame, "set-size", builtin::set_size);
         add_built_in(&mut frame, "string-concatenate", builtin::string_concat);
         add_built_in(&mut frame, "display", builtin::display);
+        add_built_in(&mut frame, "write", builtin::write);
+        add_built_in(&mut frame, "write-shared", builtin::write_shared);
+        add_built_in(&mut frame, "write-simple", builtin::write_simple);
         add_built_in(&mut frame, "map", builtin::map);
         add_built_in(&mut frame, "apply", builtin::apply);
+        add_built_in(&mut frame, "procedure?", builtin::is_procedure);
+        add_built_in(
+            &mut frame,
+            "environment-bound-names",
+            builtin::environment_bound_names,
+        );
+        add_built_in(&mut frame, "environment-ref", builtin::environment_ref);
+        add_built_in(
+            &mut frame,
+            "environment-assign!",
+            builtin::environment_assign,
+        );
+        add_built_in(&mut frame, "read", builtin::read);
+        add_built_in(&mut frame, "eval", builtin::eval);
+        add_built_in(&mut frame, "procedure-arity", builtin::procedure_arity);
+        add_built_in(&mut frame, "procedure-name", builtin::procedure_name);
+        add_built_in(&mut frame, "procedure-source", builtin::procedure_source);
+        add_built_in(
+            &mut frame,
+            "procedure-environment",
+            builtin::procedure_environment,
+        );
         add_built_in(&mut frame, "read-file", builtin::read_file);
         add_built_in(&mut frame, "split-string", builtin::split_string);
         add_built_in(&mut frame, "split-string-with", builtin::split_string_with);
         add_built_in(&mut frame, "substring", builtin::substring);
         add_built_in(&mut frame, "string-ref", builtin::string_ref);
-        add_built_in(&mut frame, "string->int", builtin::parse_int);
+        add_built_in(&mut frame, "string->list", builtin::string_to_list);
+        add_built_in(&mut frame, "list->string", builtin::list_to_string);
+        add_built_in(&mut frame, "char?", builtin::is_char);
+        add_built_in(&mut frame, "char->integer", builtin::char_to_integer);
+        add_built_in(&mut frame, "integer->char", builtin::integer_to_char);
+        add_built_in(&mut frame, "char-upcase", builtin::char_upcase);
+        add_built_in(&mut frame, "char-downcase", builtin::char_downcase);
+        add_built_in(&mut frame, "char-alphabetic?", builtin::is_char_alphabetic);
+        add_built_in(&mut frame, "char-numeric?", builtin::is_char_numeric);
+        add_built_in(&mut frame, "char-whitespace?", builtin::is_char_whitespace);
+        add_built_in(&mut frame, "digit-value", builtin::digit_value);
+        add_built_in(&mut frame, "string->number", builtin::string_to_number);
+        add_built_in(&mut frame, "number->string", builtin::number_to_string);
         add_built_in(&mut frame, "->string", builtin::to_string);
         add_built_in(&mut frame, "length", builtin::length);
         frame.insert("pi".into(), Value::Float(PI));
@@ -126,6 +344,48 @@ impl Scope {
         }
     }
 
+    /// Bindings of all frames except the global one, inner frames first. Bindings shadowed by an
+    /// inner frame are left out.
+    pub fn captured_bindings(scope: &Rc<Self>) -> Vec<(String, Value)> {
+        let mut bindings: Vec<(String, Value)> = vec![];
+        let mut scope = scope;
+        while let Some(outer) = &scope.outer {
+            for name in scope.variables().into_iter().sorted() {
+                if !bindings.iter().any(|(bound, _)| *bound == name) {
+                    let value = scope.frame.borrow()[&name].clone();
+                    bindings.push((name, value));
+                }
+            }
+            scope = outer;
+        }
+        bindings
+    }
+
+    /// Names visible in the scope, sorted
+    pub fn bound_names(scope: &Rc<Self>) -> Vec<String> {
+        let mut names = scope.variables();
+        if let Some(outer) = &scope.outer {
+            names.extend(Self::bound_names(outer));
+        }
+        names.into_iter().sorted().dedup().collect()
+    }
+
+    pub fn is_bound(scope: &Rc<Self>, name: &str) -> bool {
+        scope.frame.borrow().contains_key(name)
+            || scope
+                .outer
+                .as_ref()
+                .is_some_and(|outer| Self::is_bound(outer, name))
+    }
+
+    /// The outermost scope, the one with the built-ins and top-level definitions
+    pub fn global_of(scope: &Rc<Self>) -> Rc<Self> {
+        match &scope.outer {
+            Some(outer) => Self::global_of(outer),
+            None => scope.clone(),
+        }
+    }
+
     pub fn variables(&self) -> Vec<String> {
         self.frame.borrow().keys().cloned().collect()
     }
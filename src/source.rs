@@ -0,0 +1,114 @@
+use logos::Span;
+use std::{fmt::Display, rc::Rc};
+
+/// Program text together with the name it is reported under, usually the path of the script
+pub struct Source {
+    pub name: String,
+    pub text: String,
+    line_starts: Vec<usize>,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Rc<Self> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Rc::new(Source {
+            name: name.to_string(),
+            text: text.to_string(),
+            line_starts,
+        })
+    }
+
+    pub fn location(self: &Rc<Self>, span: Span) -> Location {
+        let line = match self.line_starts.binary_search(&span.start) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column = self.text[self.line_starts[line]..span.start]
+            .chars()
+            .count()
+            + 1;
+        Location {
+            source: self.clone(),
+            span,
+            line: line + 1,
+            column,
+        }
+    }
+
+    /// Empty location just after the last character, used when the input ends unexpectedly
+    pub fn end(self: &Rc<Self>) -> Location {
+        self.location(self.text.len()..self.text.len())
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.text.len(), |next| next - 1);
+        self.text[start..end].trim_end_matches('\r')
+    }
+}
+
+// the source text is left out on purpose, every location refers to it
+impl std::fmt::Debug for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Source({:?})", self.name)
+    }
+}
+
+/// Position of an expression or token in the program, lines and columns are numbered from 1
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub source: Rc<Source>,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Location covering both `self` and `other`
+    pub fn to(&self, other: &Location) -> Location {
+        Location {
+            span: self.span.start..other.span.end,
+            ..self.clone()
+        }
+    }
+
+    /// Writes the message followed by the location and the offending source line with the
+    /// located part underlined
+    pub fn fmt_message(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        message: &dyn Display,
+    ) -> std::fmt::Result {
+        let line = self.source.line_text(self.line);
+        let gutter = " ".repeat(self.line.to_string().len());
+        // keep tabs so that the caret lines up with the excerpt
+        let indent: String = line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underlined = line
+            .chars()
+            .skip(self.column - 1)
+            .take(self.source.text[self.span.clone()].chars().count())
+            .count()
+            .max(1);
+
+        writeln!(f, "{message}")?;
+        writeln!(f, "{gutter}--> {self}")?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {line}", self.line)?;
+        write!(f, "{gutter} | {indent}{}", "^".repeat(underlined))
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.source.name, self.line, self.column)
+    }
+}
@@ -1,41 +1,98 @@
 use crate::{
-    eval::{RuntimeError, evaluate, evaluate_list},
-    parser::Expr,
+    eval::{RuntimeError, count_arguments, datum, evaluate, evaluate_list},
+    parser::{Expr, ExprKind},
     scope::Scope,
     tokenizer::Token,
-    value::{Procedure, Value},
+    value::{Procedure, RecordProcedure, RecordType, Value},
 };
 use std::rc::Rc;
 
 pub fn func_call(func: &Value, params: Vec<Value>) -> Result<Value, RuntimeError> {
+    // arguments of built-ins are consumed by the call, so only their number is recorded
+    let passed = params.len();
     match func {
-        Value::BuiltIn(func) => func.call(params),
+        Value::BuiltIn(builtin) => builtin
+            .call(params)
+            .map_err(|e| e.in_frame(builtin.name.to_string(), count_arguments(passed))),
+        // procedures record their own frames with values of the arguments
         Value::Procedure(proc) => proc.call(params),
-        _ => Err(RuntimeError::NotProcedure),
+        Value::RecordProcedure(proc) => proc
+            .call(params)
+            .map_err(|e| e.in_frame(func.to_string(), count_arguments(passed))),
+        _ => Err(RuntimeError::NotProcedure(func.clone())),
     }
 }
 
 pub fn begin(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
-    evaluate_list(exprs, scope)?.ok_or(RuntimeError::IllFormedSpecialForm)
+    evaluate_list(exprs, scope)?.ok_or(RuntimeError::IllFormedSpecialForm("begin"))
 }
 
 pub fn if_statement(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
-    if exprs.len() != 3 {
-        return Err(RuntimeError::IllFormedSpecialForm);
+    if exprs.len() != 2 && exprs.len() != 3 {
+        return Err(RuntimeError::IllFormedSpecialForm("if"));
     }
     let cond = evaluate(&exprs[0], scope)?.truthy();
     if cond {
         evaluate(&exprs[1], scope)
     } else {
-        evaluate(&exprs[2], scope)
+        // value of `if` without alternative is unspecified when condition is false
+        exprs.get(2).map_or(Ok(Value::Unspecified), |alternative| {
+            evaluate(alternative, scope)
+        })
+    }
+}
+
+/// `(the-environment)` evaluates to the environment it is evaluated in
+pub fn the_environment(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    if !exprs.is_empty() {
+        return Err(RuntimeError::IllFormedSpecialForm("the-environment"));
+    }
+    Ok(Value::Environment(scope.clone()))
+}
+
+/// `(interaction-environment)` evaluates to the global environment. It needs to reach the scope
+/// it is called from, so unlike in R7RS it is a special form rather than a procedure.
+pub fn interaction_environment(
+    exprs: &[Rc<Expr>],
+    scope: &Rc<Scope>,
+) -> Result<Value, RuntimeError> {
+    if !exprs.is_empty() {
+        return Err(RuntimeError::IllFormedSpecialForm(
+            "interaction-environment",
+        ));
+    }
+    Ok(Value::Environment(Scope::global_of(scope)))
+}
+
+/// `(defined? symbol [environment])` tells whether the symbol is bound in the environment, which
+/// defaults to the current one. Both operands are evaluated.
+pub fn is_defined(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    let (symbol, environment) = match exprs {
+        [symbol] => (evaluate(symbol, scope)?, scope.clone()),
+        [symbol, environment] => match evaluate(environment, scope)? {
+            Value::Environment(environment) => (evaluate(symbol, scope)?, environment),
+            v => return Err(RuntimeError::EnvironmentExpected(v)),
+        },
+        _ => return Err(RuntimeError::IllFormedSpecialForm("defined?")),
+    };
+    match symbol {
+        Value::Symbol(symbol) => Ok(Value::Bool(Scope::is_bound(&environment, &symbol))),
+        v => Err(RuntimeError::SymbolExpected(v)),
+    }
+}
+
+pub fn quote(exprs: &[Rc<Expr>], _scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    match exprs {
+        [expr] => datum(expr),
+        _ => Err(RuntimeError::IllFormedSpecialForm("quote")),
     }
 }
 
 fn extract_symbols(params: &[Rc<Expr>]) -> Result<Vec<String>, RuntimeError> {
     params
         .iter()
-        .map(|param| match param.as_ref() {
-            Expr::Token(Token::Symbol(param_name)) => Ok(param_name.clone()),
+        .map(|param| match &param.kind {
+            ExprKind::Token(Token::Symbol(param_name)) => Ok(param_name.clone()),
             _ => Err(RuntimeError::ParameterMustBeIdentifier),
         })
         .collect()
@@ -43,25 +100,33 @@ fn extract_symbols(params: &[Rc<Expr>]) -> Result<Vec<String>, RuntimeError> {
 
 pub fn lambda(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
     if exprs.len() < 2 {
-        return Err(RuntimeError::IllFormedSpecialForm);
+        return Err(RuntimeError::IllFormedSpecialForm("lambda"));
     }
-    let params = &exprs[0].as_ref();
+    let params = &exprs[0].kind;
     let body = exprs.iter().skip(1).cloned().collect();
-    if let Expr::List(params) = params {
+    if let ExprKind::List(params) = params {
         let param_names = extract_symbols(params)?;
-        Ok(Value::Procedure(Procedure::new(
+        Ok(Value::Procedure(Rc::new(Procedure::new(
+            None,
             param_names,
             body,
             scope.clone(),
-        )))
+        ))))
     } else {
         // lambda also accepts single symbol as a parameter when it is the only one
         // but I don't intend to implement this variant - I like when there is just
         // one way to do things
-        Err(RuntimeError::IllFormedSpecialForm)
+        Err(RuntimeError::IllFormedSpecialForm("lambda"))
     }
 }
 
+pub fn set(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    if exprs.is_empty() {
+        return Err(RuntimeError::IllFormedSpecialForm("set!"));
+    }
+    define_variable(exprs, scope, DefineBehavior::SetValueOfExisting)
+}
+
 pub enum DefineBehavior {
     DefineNew,
     SetValueOfExisting,
@@ -72,20 +137,33 @@ pub fn define_variable(
     scope: &Rc<Scope>,
     behavior: DefineBehavior,
 ) -> Result<Value, RuntimeError> {
-    // this function is called from define() below where non-emptiness of `exprs` is checked
+    // this function is called from set() above and define() below where non-emptiness of `exprs`
+    // is checked
     let symbol = &exprs[0];
 
-    if let Expr::Token(Token::Symbol(symbol)) = symbol.as_ref() {
-        let rhs_expr = exprs.get(1).ok_or(RuntimeError::IllFormedSpecialForm)?;
-        let rhs_val = evaluate(rhs_expr, scope)?;
+    if let ExprKind::Token(Token::Symbol(symbol)) = &symbol.kind {
+        let form = match behavior {
+            DefineBehavior::DefineNew => "define",
+            DefineBehavior::SetValueOfExisting => "set!",
+        };
+        let rhs_expr = exprs
+            .get(1)
+            .ok_or(RuntimeError::IllFormedSpecialForm(form))?;
+        let rhs_val = match evaluate(rhs_expr, scope)? {
+            // `(define name (lambda ...))` names the procedure like `(define (name ...) ...)`
+            Value::Procedure(proc) if matches!(behavior, DefineBehavior::DefineNew) => {
+                Value::Procedure(proc.or_named(symbol))
+            }
+            value => value,
+        };
 
         match behavior {
             DefineBehavior::SetValueOfExisting => Scope::set(scope, symbol, rhs_val)
-                .map(|_| Value::Nil)
+                .map(|_| Value::Unspecified)
                 .map_err(|_| RuntimeError::UnboundVariable(symbol.to_string())),
             DefineBehavior::DefineNew => {
                 Scope::define(scope, symbol, rhs_val);
-                Ok(Value::Nil)
+                Ok(Value::Unspecified)
             }
         }
     } else {
@@ -95,9 +173,9 @@ pub fn define_variable(
 
 fn define_procedure(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
     // this function is called from define() below where non-emptiness of `exprs` is checked
-    let symbol_and_params = exprs[0].as_ref();
+    let symbol_and_params = &exprs[0].kind;
 
-    if let Expr::List(symbol_and_params) = symbol_and_params {
+    if let ExprKind::List(symbol_and_params) = symbol_and_params {
         let symbol_and_params = extract_symbols(symbol_and_params)?;
         let symbol = symbol_and_params
             .first()
@@ -106,24 +184,107 @@ fn define_procedure(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, Runt
         let param_names = symbol_and_params.into_iter().skip(1).collect();
         let body = exprs.iter().skip(1).cloned().collect::<Vec<_>>();
         if body.is_empty() {
-            return Err(RuntimeError::IllFormedSpecialForm);
+            return Err(RuntimeError::IllFormedSpecialForm("define"));
         }
 
-        let procedure = Value::Procedure(Procedure::new(param_names, body, scope.clone()));
+        let procedure = Procedure::new(Some(&symbol), param_names, body, scope.clone());
+        let procedure = Value::Procedure(Rc::new(procedure));
         Scope::define(scope, &symbol, procedure);
-        Ok(Value::Nil)
+        Ok(Value::Unspecified)
     } else {
-        Err(RuntimeError::IllFormedSpecialForm)
+        Err(RuntimeError::IllFormedSpecialForm("define"))
     }
 }
 
+fn record_procedure(
+    name: &Rc<Expr>,
+    procedure: RecordProcedure,
+    scope: &Rc<Scope>,
+) -> Result<(), RuntimeError> {
+    let [name] = extract_symbols(std::slice::from_ref(name))?
+        .try_into()
+        .map_err(|_| RuntimeError::IllFormedSpecialForm("define-record-type"))?;
+    Scope::define(scope, &name, Value::RecordProcedure(Rc::new(procedure)));
+    Ok(())
+}
+
+pub fn define_record_type(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    // (define-record-type <name> (constructor field ...) predicate (field accessor [modifier]) ...)
+    let [type_name, constructor, predicate, fields @ ..] = exprs else {
+        return Err(RuntimeError::IllFormedSpecialForm("define-record-type"));
+    };
+    let [type_name] = extract_symbols(std::slice::from_ref(type_name))?
+        .try_into()
+        .map_err(|_| RuntimeError::IllFormedSpecialForm("define-record-type"))?;
+
+    let fields = fields
+        .iter()
+        .map(|field| match &field.kind {
+            ExprKind::List(field) if (1..=3).contains(&field.len()) => Ok(field),
+            _ => Err(RuntimeError::IllFormedSpecialForm("define-record-type")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let field_names = fields
+        .iter()
+        .map(|field| extract_symbols(&field[..1]).map(|mut name| name.remove(0)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let constructor = match &constructor.kind {
+        ExprKind::List(constructor) if !constructor.is_empty() => constructor,
+        _ => return Err(RuntimeError::IllFormedSpecialForm("define-record-type")),
+    };
+    let indices = extract_symbols(&constructor[1..])?
+        .iter()
+        .map(|param| {
+            field_names
+                .iter()
+                .position(|name| name == param)
+                .ok_or(RuntimeError::IllFormedSpecialForm("define-record-type"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let record_type = Rc::new(RecordType {
+        name: type_name.clone(),
+        field_names,
+    });
+    Scope::define(scope, &type_name, Value::RecordType(record_type.clone()));
+    record_procedure(
+        &constructor[0],
+        RecordProcedure::Constructor(record_type.clone(), indices),
+        scope,
+    )?;
+    record_procedure(
+        predicate,
+        RecordProcedure::Predicate(record_type.clone()),
+        scope,
+    )?;
+    for (index, field) in fields.iter().enumerate() {
+        if let Some(accessor) = field.get(1) {
+            record_procedure(
+                accessor,
+                RecordProcedure::Accessor(record_type.clone(), index),
+                scope,
+            )?;
+        }
+        if let Some(modifier) = field.get(2) {
+            record_procedure(
+                modifier,
+                RecordProcedure::Modifier(record_type.clone(), index),
+                scope,
+            )?;
+        }
+    }
+    Ok(Value::Unspecified)
+}
+
 pub fn define(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
     match exprs.first() {
-        Some(expr) => match expr.as_ref() {
-            Expr::Token(_) => define_variable(exprs, scope, DefineBehavior::DefineNew),
-            Expr::List(_) => define_procedure(exprs, scope),
+        Some(expr) => match &expr.kind {
+            ExprKind::Token(_) => define_variable(exprs, scope, DefineBehavior::DefineNew),
+            ExprKind::List(_) => define_procedure(exprs, scope),
+            ExprKind::Vector(_) => Err(RuntimeError::IdentifierExpected),
         },
-        _ => Err(RuntimeError::IllFormedSpecialForm),
+        _ => Err(RuntimeError::IllFormedSpecialForm("define")),
     }
 }
 
@@ -131,15 +292,22 @@ fn expr2params(expr: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Vec<Value>, Runti
     expr.iter().map(|param| evaluate(param, scope)).collect()
 }
 
+/// Special forms get their operands unevaluated, procedures get evaluated parameters
+fn invoke(func: &Value, exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+    match func {
+        Value::SpecialForm(form) => form.call(exprs, scope),
+        func => func_call(func, expr2params(exprs, scope)?),
+    }
+}
+
 pub fn invoke_named_function(
     exprs: &[Rc<Expr>],
     scope: &Rc<Scope>,
     symbol: &str,
 ) -> Result<Value, RuntimeError> {
-    let params = expr2params(exprs, scope)?;
     let func = Scope::get(scope, symbol)
         .ok_or_else(|| RuntimeError::UnboundVariable(symbol.to_string()))?;
-    func_call(&func, params)
+    invoke(&func, exprs, scope)
 }
 
 pub fn invoke_lambda(
@@ -148,14 +316,13 @@ pub fn invoke_lambda(
     scope: &Rc<Scope>,
 ) -> Result<Value, RuntimeError> {
     let func = evaluate(body, scope)?;
-    let params = expr2params(params, scope)?;
-    func_call(&func, params)
+    invoke(&func, params, scope)
 }
 
 fn parse_binding(init_expr: &Rc<Expr>) -> Result<[Rc<Expr>; 3], RuntimeError> {
-    let init_expr = match init_expr.as_ref() {
-        Expr::List(init_expr) if init_expr.len() == 3 => init_expr,
-        _ => return Err(RuntimeError::IllFormedSpecialForm),
+    let init_expr = match &init_expr.kind {
+        ExprKind::List(init_expr) if init_expr.len() == 3 => init_expr,
+        _ => return Err(RuntimeError::IllFormedSpecialForm("do")),
     };
 
     Ok([
@@ -168,24 +335,24 @@ fn parse_binding(init_expr: &Rc<Expr>) -> Result<[Rc<Expr>; 3], RuntimeError> {
 pub fn do_loop(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
     // (do ((symbol init value) ...) (test_cond expr1 expr2 ...) expr1 expr2 ...)
     if exprs.len() < 2 {
-        return Err(RuntimeError::IllFormedSpecialForm);
+        return Err(RuntimeError::IllFormedSpecialForm("do"));
     }
 
     // Vec<(symbol init value)>
-    let init: Vec<_> = match exprs[0].as_ref() {
-        Expr::List(list) => list,
+    let init: Vec<_> = match &exprs[0].kind {
+        ExprKind::List(list) => list,
         _ => {
-            return Err(RuntimeError::IllFormedSpecialForm);
+            return Err(RuntimeError::IllFormedSpecialForm("do"));
         }
     }
     .iter()
     .map(parse_binding)
     .collect::<Result<_, _>>()?;
 
-    let test = match exprs[1].as_ref() {
-        Expr::List(list) if !list.is_empty() => list,
+    let test = match &exprs[1].kind {
+        ExprKind::List(list) if !list.is_empty() => list,
         _ => {
-            return Err(RuntimeError::IllFormedSpecialForm);
+            return Err(RuntimeError::IllFormedSpecialForm("do"));
         }
     };
 
@@ -206,7 +373,7 @@ pub fn do_loop(exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeEr
             let ans = if test.len() > 1 {
                 begin(&test[1..], &scope)?
             } else {
-                Value::Nil
+                Value::Unspecified
             };
             return Ok(ans);
         }
@@ -1,27 +1,16 @@
-use logos::{Logos, Span};
+use logos::{Logos, Skip, Span};
 use std::{convert::Infallible, fmt::Display, rc::Rc, str::FromStr};
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub enum LexingError {
     NumberParseError,
     InvalidEscape(char),
+    InvalidCharacter(String),
     UnexpectedEof,
     #[default]
     Other,
 }
 
-impl From<std::num::ParseIntError> for LexingError {
-    fn from(_: std::num::ParseIntError) -> Self {
-        LexingError::NumberParseError
-    }
-}
-
-impl From<std::num::ParseFloatError> for LexingError {
-    fn from(_: std::num::ParseFloatError) -> Self {
-        LexingError::NumberParseError
-    }
-}
-
 impl From<Infallible> for LexingError {
     fn from(_: Infallible) -> Self {
         LexingError::Other
@@ -33,7 +22,11 @@ impl Display for LexingError {
         match self {
             LexingError::NumberParseError => write!(f, "Invalid number format"),
             LexingError::InvalidEscape(c) => {
-                write!(f, "Invalid escape character {}", c)
+                // whitespace after a backslash would be invisible
+                write!(f, "Invalid escape character {}", c.escape_debug())
+            }
+            LexingError::InvalidCharacter(name) => {
+                write!(f, "Invalid character literal #\\{}", name)
             }
             LexingError::UnexpectedEof => write!(f, "Unexpected EOF"),
             LexingError::Other => write!(f, "Unspecified lexing error"),
@@ -41,13 +34,37 @@ impl Display for LexingError {
     }
 }
 
-fn parse_string(lex: &mut logos::Lexer<Token>) -> Result<Rc<String>, LexingError> {
-    let slice = lex.slice(); // includes quotes
-    let inner = &slice[1..slice.len() - 1]; // remove outer quotes
+fn parse_hex_escape(hex: &str, escape: char) -> Result<char, LexingError> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or(LexingError::InvalidEscape(escape))
+}
 
-    // Unescape using Rust's own unescape rules (simple version):
+/// Hexadecimal digits of an escape up to its terminator, which must be present
+fn hex_digits(
+    chars: &mut impl Iterator<Item = char>,
+    terminator: char,
+    escape: char,
+) -> Result<String, LexingError> {
+    let mut hex = String::new();
+    for c in chars {
+        if c == terminator {
+            return Ok(hex);
+        }
+        hex.push(c);
+    }
+    Err(LexingError::InvalidEscape(escape))
+}
+
+fn is_intraline_whitespace(c: &char) -> bool {
+    *c == ' ' || *c == '\t'
+}
+
+/// Resolves backslash escapes in string literals and `|...|` symbols
+fn unescape(inner: &str) -> Result<String, LexingError> {
     let mut result = String::new();
-    let mut chars = inner.chars();
+    let mut chars = inner.chars().peekable();
 
     while let Some(c) = chars.next() {
         if c == '\\' {
@@ -55,8 +72,39 @@ fn parse_string(lex: &mut logos::Lexer<Token>) -> Result<Rc<String>, LexingError
                 Some('n') => result.push('\n'),
                 Some('t') => result.push('\t'),
                 Some('r') => result.push('\r'),
+                Some('a') => result.push('\u{7}'),
+                Some('b') => result.push('\u{8}'),
+                Some('0') => result.push('\0'),
                 Some('"') => result.push('"'),
                 Some('\\') => result.push('\\'),
+                Some('|') => result.push('|'),
+                Some('x') => {
+                    // \xHH; where HH is hexadecimal code point
+                    let hex = hex_digits(&mut chars, ';', 'x')?;
+                    result.push(parse_hex_escape(&hex, 'x')?);
+                }
+                Some('u') => {
+                    // \u{HHHH}
+                    if chars.next() != Some('{') {
+                        return Err(LexingError::InvalidEscape('u'));
+                    }
+                    let hex = hex_digits(&mut chars, '}', 'u')?;
+                    result.push(parse_hex_escape(&hex, 'u')?);
+                }
+                Some(ch) if ch == '\n' || ch == '\r' || is_intraline_whitespace(&ch) => {
+                    // line continuation: \<whitespace><line ending><indentation> is removed
+                    let mut end = ch;
+                    while is_intraline_whitespace(&end) {
+                        end = chars.next().ok_or(LexingError::UnexpectedEof)?;
+                    }
+                    if end == '\r' {
+                        end = chars.next_if_eq(&'\n').unwrap_or(end);
+                    }
+                    if end != '\n' {
+                        return Err(LexingError::InvalidEscape(ch));
+                    }
+                    while chars.next_if(is_intraline_whitespace).is_some() {}
+                }
                 Some(ch) => return Err(LexingError::InvalidEscape(ch)),
                 None => return Err(LexingError::UnexpectedEof),
             }
@@ -65,7 +113,180 @@ fn parse_string(lex: &mut logos::Lexer<Token>) -> Result<Rc<String>, LexingError
         }
     }
 
-    Ok(Rc::new(result))
+    Ok(result)
+}
+
+fn parse_string(lex: &mut logos::Lexer<Token>) -> Result<Rc<String>, LexingError> {
+    let slice = lex.slice(); // includes quotes
+    let inner = &slice[1..slice.len() - 1]; // remove outer quotes
+    Ok(Rc::new(unescape(inner)?))
+}
+
+/// Raw string literal `#r"..."` without any escapes, may span multiple lines. The opening may
+/// contain any number of `#` after `r` (`#r##"..."##`) so that the text can contain `"#`.
+fn parse_raw_string(lex: &mut logos::Lexer<Token>) -> Result<Rc<String>, LexingError> {
+    let hashes = lex.slice().len() - 3; // opening is #r<hashes>"
+    let terminator = format!("\"{}", "#".repeat(hashes));
+    let remainder = lex.remainder();
+    match remainder.find(&terminator) {
+        Some(end) => {
+            let content = remainder[..end].to_string();
+            lex.bump(end + terminator.len());
+            Ok(Rc::new(content))
+        }
+        None => {
+            lex.bump(remainder.len());
+            Err(LexingError::UnexpectedEof)
+        }
+    }
+}
+
+fn parse_quoted_symbol(lex: &mut logos::Lexer<Token>) -> Result<String, LexingError> {
+    let slice = lex.slice(); // includes vertical bars
+    unescape(&slice[1..slice.len() - 1])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Exactness {
+    Exact,
+    Inexact,
+}
+
+fn strip_underscores(digits: &str) -> Result<String, LexingError> {
+    // underscores are allowed only as separators between two digits
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err(LexingError::NumberParseError);
+    }
+    Ok(digits.replace('_', ""))
+}
+
+/// Parses numeric literal in R7RS syntax: optional radix (`#x`, `#b`, `#o`, `#d`) and exactness
+/// (`#e`, `#i`) prefixes in any order, sign, decimal point and exponent, `+inf.0`, `-nan.0` and
+/// `_` digit separators. Numbers without radix prefix are read in `default_radix` (2 to 36).
+pub fn parse_number(text: &str, default_radix: u32) -> Result<Number, LexingError> {
+    let mut radix = None;
+    let mut exactness = None;
+    let mut rest = text;
+
+    while let Some(prefix) = rest.strip_prefix('#') {
+        let mut chars = prefix.chars();
+        match chars.next().map(|c| c.to_ascii_lowercase()) {
+            Some(c @ ('x' | 'b' | 'o' | 'd')) if radix.is_none() => {
+                radix = Some(match c {
+                    'x' => 16,
+                    'b' => 2,
+                    'o' => 8,
+                    _ => 10,
+                })
+            }
+            Some('e') if exactness.is_none() => exactness = Some(Exactness::Exact),
+            Some('i') if exactness.is_none() => exactness = Some(Exactness::Inexact),
+            _ => return Err(LexingError::NumberParseError),
+        }
+        rest = chars.as_str();
+    }
+    let radix = radix.unwrap_or(default_radix);
+
+    let (negative, body) = match rest.as_bytes().first() {
+        Some(b'-') => (true, &rest[1..]),
+        Some(b'+') => (false, &rest[1..]),
+        _ => (false, rest),
+    };
+    let signed = body.len() != rest.len();
+    if body.starts_with(['+', '-']) {
+        return Err(LexingError::NumberParseError);
+    }
+    let sign = if negative { -1.0 } else { 1.0 };
+
+    let number = match body.to_ascii_lowercase().as_str() {
+        // infinities and NaNs must be written with an explicit sign
+        "inf.0" if signed => Number::Float(sign * f64::INFINITY),
+        "nan.0" if signed => Number::Float(f64::NAN),
+        "" => return Err(LexingError::NumberParseError),
+        body if radix == 10 && body.contains(['.', 'e']) => {
+            let float: f64 = strip_underscores(body)?
+                .parse()
+                .map_err(|_| LexingError::NumberParseError)?;
+            Number::Float(sign * float)
+        }
+        body => {
+            let digits = strip_underscores(body)?;
+            let int = i64::from_str_radix(
+                &format!("{}{digits}", if negative { "-" } else { "" }),
+                radix,
+            )
+            .map_err(|_| LexingError::NumberParseError)?;
+            Number::Int(int)
+        }
+    };
+
+    match (exactness, number) {
+        (Some(Exactness::Inexact), Number::Int(i)) => Ok(Number::Float(i as f64)),
+        (Some(Exactness::Exact), Number::Float(f)) => {
+            if f.is_finite() && f.fract() == 0.0 && f.abs() < i64::MAX as f64 {
+                Ok(Number::Int(f as i64))
+            } else {
+                Err(LexingError::NumberParseError)
+            }
+        }
+        (_, number) => Ok(number),
+    }
+}
+
+fn parse_char(lex: &mut logos::Lexer<Token>) -> Result<char, LexingError> {
+    let name = &lex.slice()[2..]; // remove leading #\
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(c);
+    }
+
+    let c = match name {
+        "alarm" => Some('\u{7}'),
+        "backspace" => Some('\u{8}'),
+        "delete" => Some('\u{7f}'),
+        "escape" => Some('\u{1b}'),
+        "newline" => Some('\n'),
+        "null" | "nul" => Some('\0'),
+        "return" => Some('\r'),
+        "space" => Some(' '),
+        "tab" => Some('\t'),
+        _ => name
+            .strip_prefix('x')
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32),
+    };
+    c.ok_or_else(|| LexingError::InvalidCharacter(name.to_string()))
+}
+
+/// Skips block comment which may contain other nested block comments
+fn skip_block_comment(lex: &mut logos::Lexer<Token>) -> Result<Skip, LexingError> {
+    // bytes rather than chars, the delimiters are ASCII and cannot split a character
+    let remainder = lex.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < remainder.len() {
+        if remainder[i..].starts_with(b"|#") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                lex.bump(i);
+                return Ok(Skip);
+            }
+        } else if remainder[i..].starts_with(b"#|") {
+            depth += 1;
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    lex.bump(remainder.len());
+    Err(LexingError::UnexpectedEof)
 }
 
 fn parse_bool(lex: &mut logos::Lexer<Token>) -> Result<bool, LexingError> {
@@ -82,41 +303,137 @@ fn parse_bool(lex: &mut logos::Lexer<Token>) -> Result<bool, LexingError> {
 pub enum Token {
     #[token("(")]
     LBracket,
+    #[token("#(")]
+    VectorStart,
     #[token(")")]
     RBracket,
-    #[token("begin")]
-    Begin,
-    #[token("define")]
-    Define,
-    #[token("do")]
-    Do,
-    #[token("set!")]
-    Set,
-    #[token("lambda")]
-    Lambda,
-    #[token("if")]
-    If,
-    #[regex(r#""([^"\\]|\\.)*""#, parse_string)]
+    #[token("'")]
+    QuoteMark,
+    #[regex(r#""([^"\\]|\\(.|\n))*""#, parse_string)]
+    #[regex(r##"#r#*""##, parse_raw_string)]
     StringLiteral(Rc<String>),
-    #[regex("-?[0-9]+", |lex| lex.slice().parse(), priority=3)]
-    Int(i64),
-    #[regex(r"[\*\+\-/=<>a-zA-Z\?]+[\*\+\-/=<>a-zA-Z\?0-9]*", |lex| String::from_str(lex.slice()))]
+    #[regex(r"[+-]?([0-9][0-9_]*(\.[0-9_]*)?|\.[0-9][0-9_]*)([eE][+-]?[0-9]+)?", |lex| parse_number(lex.slice(), 10), priority = 3)]
+    #[regex(r"[+-]([iI][nN][fF]|[nN][aA][nN])\.0", |lex| parse_number(lex.slice(), 10), priority = 10)]
+    #[regex(r"#[xXbBoOdDeEiI](#[xXbBoOdDeEiI])?[+-]?[0-9a-zA-Z_.]+", |lex| parse_number(lex.slice(), 10))]
+    Number(Number),
+    // R7RS identifiers: <initial> <subsequent>*, peculiar identifiers starting with sign or dot
+    // (`+`, `-`, `->x`, `...`) and identifiers enclosed in vertical lines
+    #[regex(r"[\p{L}!$%&*/:<=>?^_~][\p{L}\p{N}\p{M}!$%&*/:<=>?^_~+\-.@]*", |lex| String::from_str(lex.slice()))]
+    #[regex(r"[+-]", |lex| String::from_str(lex.slice()))]
+    #[regex(r"[+-][\p{L}!$%&*/:<=>?^_~+\-@][\p{L}\p{N}\p{M}!$%&*/:<=>?^_~+\-.@]*", |lex| String::from_str(lex.slice()))]
+    #[regex(r"[+-]?\.[\p{L}!$%&*/:<=>?^_~+\-@.][\p{L}\p{N}\p{M}!$%&*/:<=>?^_~+\-.@]*", |lex| String::from_str(lex.slice()))]
+    #[regex(r"\|([^|\\]|\\.)*\|", parse_quoted_symbol)]
     Symbol(String),
-    #[regex("-?([0-9]+[.]([0-9]*)?|[.][0-9]+)", |lex| lex.slice().parse())]
-    Float(f64),
+    #[regex(r"#\\([a-zA-Z0-9]+|.)", parse_char)]
+    Char(char),
     #[regex("#[tf]", parse_bool)]
     Bool(bool),
     #[regex(r";[^\n]*", logos::skip)]
+    #[token("#|", skip_block_comment)]
     Comment,
+    /// `#;` comments out the following datum, it is removed by the parser
+    #[token("#;")]
+    DatumComment,
     #[regex(r"[ \t\n\f]+", logos::skip)]
     WhiteSpace,
 }
 
+/// Writes the string as a literal that reads back to the same string
+pub fn escape_string(s: &str) -> String {
+    format!("\"{}\"", escape(s, '"'))
+}
+
+/// Escapes backslashes, the delimiter and control characters, the inverse of `unescape`
+fn escape(s: &str, delimiter: char) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '\\' => result.push_str("\\\\"),
+            c if c == delimiter => {
+                result.push('\\');
+                result.push(c);
+            }
+            c if c.is_control() => result.push_str(&format!("\\x{:x};", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Writes the symbol as an identifier, enclosed in vertical lines when it would not read back
+/// as the same symbol
+pub fn symbol_to_string(symbol: &str) -> String {
+    let mut lexer = Token::lexer(symbol);
+    match (lexer.next(), lexer.next()) {
+        (Some(Ok(Token::Symbol(s))), None) if s == symbol && !symbol.starts_with('|') => {
+            symbol.to_string()
+        }
+        _ => format!("|{}|", escape(symbol, '|')),
+    }
+}
+
+/// Writes the character literal, the inverse of `parse_char`
+pub fn char_to_string(c: char) -> String {
+    let name = match c {
+        '\u{7}' => "alarm",
+        '\u{8}' => "backspace",
+        '\u{7f}' => "delete",
+        '\u{1b}' => "escape",
+        '\n' => "newline",
+        '\0' => "null",
+        '\r' => "return",
+        ' ' => "space",
+        '\t' => "tab",
+        c if c.is_control() => return format!("#\\x{:x}", c as u32),
+        c => return format!("#\\{c}"),
+    };
+    format!("#\\{name}")
+}
+
+pub fn float_to_string(float: f64) -> String {
+    if float.is_nan() {
+        "+nan.0".to_string()
+    } else if float.is_infinite() {
+        if float > 0.0 { "+inf.0" } else { "-inf.0" }.to_string()
+    } else {
+        // Debug formatting keeps the decimal point for integral values (`1.0`)
+        format!("{float:?}")
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LBracket => write!(f, "("),
+            Token::VectorStart => write!(f, "#("),
+            Token::RBracket => write!(f, ")"),
+            Token::QuoteMark => write!(f, "'"),
+            Token::StringLiteral(s) => write!(f, "{}", escape_string(s)),
+            Token::Number(Number::Int(i)) => write!(f, "{i}"),
+            Token::Number(Number::Float(x)) => write!(f, "{}", float_to_string(*x)),
+            Token::Symbol(s) => write!(f, "{}", symbol_to_string(s)),
+            Token::Char(c) => write!(f, "{}", char_to_string(*c)),
+            Token::Bool(true) => write!(f, "#t"),
+            Token::Bool(false) => write!(f, "#f"),
+            Token::Comment => write!(f, ";"),
+            Token::DatumComment => write!(f, "#;"),
+            Token::WhiteSpace => write!(f, " "),
+        }
+    }
+}
+
 type Tokens = Vec<(Token, Span)>;
 type LexingErrors = Vec<(LexingError, Span)>;
 
 pub fn tokenize(src: &str) -> Result<Tokens, LexingErrors> {
-    let lexer = Token::lexer(src);
+    let mut lexer = Token::lexer(src);
+    if src.starts_with("#!") {
+        // ignore shebang line of executable scripts
+        lexer.bump(src.find('\n').unwrap_or(src.len()));
+    }
 
     let mut tokens = Vec::new();
     let mut errors = Vec::new();
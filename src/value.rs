@@ -1,29 +1,98 @@
 use crate::{
-    eval::{RuntimeError, evaluate_list},
-    parser::Expr,
+    eval::{Arity, RuntimeError, count_arguments, evaluate_list},
+    parser::{Expr, LINE_WIDTH},
     scope::Scope,
+    tokenizer::{char_to_string, escape_string, float_to_string, symbol_to_string},
+};
+use itertools::Itertools;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    hash::{Hash, Hasher},
+    rc::Rc,
 };
-use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Procedure {
+    /// Name the procedure was defined with, lambdas are anonymous until they are bound by `define`
+    name: Option<Rc<String>>,
     param_names: Vec<String>,
     body: Vec<Rc<Expr>>,
     scope: Rc<Scope>,
 }
 
 impl Procedure {
-    pub fn new(param_names: Vec<String>, body: Vec<Rc<Expr>>, scope: Rc<Scope>) -> Self {
+    pub fn new(
+        name: Option<&str>,
+        param_names: Vec<String>,
+        body: Vec<Rc<Expr>>,
+        scope: Rc<Scope>,
+    ) -> Self {
         Self {
+            name: name.map(|name| Rc::new(name.to_string())),
             param_names,
             body,
             scope,
         }
     }
 
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref().map(String::as_str)
+    }
+
+    /// Gives the name to an anonymous procedure, named ones keep their original name
+    pub fn or_named(self: Rc<Self>, name: &str) -> Rc<Self> {
+        match self.name {
+            Some(_) => self,
+            None => Rc::new(Self {
+                name: Some(Rc::new(name.to_string())),
+                ..(*self).clone()
+            }),
+        }
+    }
+
+    /// Bindings of the scope the procedure was created in, see `Scope::captured_bindings`
+    pub fn captured_bindings(&self) -> Vec<(String, Value)> {
+        Scope::captured_bindings(&self.scope)
+    }
+
+    /// Source code of the procedure as a lambda expression
+    pub fn source(&self) -> String {
+        let params = self
+            .param_names
+            .iter()
+            .map(|p| symbol_to_string(p))
+            .join(" ");
+        let head = format!("(lambda ({params})");
+        let flat = format!("{head} {})", self.body.iter().join(" "));
+        if flat.chars().count() <= LINE_WIDTH {
+            return flat;
+        }
+        let body = self
+            .body
+            .iter()
+            .map(|expr| format!("\n  {}", expr.pretty(2)));
+        format!("{head}{})", body.collect::<String>())
+    }
+
+    /// How the procedure is referred to in backtraces and error messages
+    fn frame_name(&self) -> String {
+        self.name().map_or_else(|| self.to_string(), str::to_string)
+    }
+
+    pub fn arity(&self) -> Arity {
+        Arity::Exactly(self.param_names.len())
+    }
+
     pub fn call(&self, params: Vec<Value>) -> Result<Value, RuntimeError> {
         if self.param_names.len() != params.len() {
-            return Err(RuntimeError::WrongNumberOfAgumentsPassed);
+            let passed = params.len();
+            let error = RuntimeError::WrongNumberOfAgumentsPassed {
+                expected: self.arity(),
+                passed,
+            };
+            return Err(error.in_frame(self.frame_name(), count_arguments(passed)));
         }
 
         let scope = Scope::nest(&self.scope);
@@ -34,18 +103,78 @@ impl Procedure {
                 Scope::define(&scope, name, param);
             });
 
-        evaluate_list(&self.body, &scope)?.ok_or(RuntimeError::IllFormedExpression)
+        evaluate_list(&self.body, &scope)
+            .and_then(|value| value.ok_or(RuntimeError::IllFormedExpression))
+            .map_err(|e| e.in_frame(self.frame_name(), self.arguments(&scope)))
+    }
+
+    /// Summary of the arguments bound in the scope of a call
+    fn arguments(&self, scope: &Rc<Scope>) -> String {
+        const MAX_LENGTH: usize = 30;
+        if self.param_names.is_empty() {
+            return "no arguments".to_string();
+        }
+        self.param_names
+            .iter()
+            .map(|name| {
+                let mut value = Truncated {
+                    text: String::new(),
+                    length: 0,
+                    limit: MAX_LENGTH,
+                };
+                // formatting fails once the value gets too long
+                match Scope::get(scope, name).map(|v| write!(value, "{v}")) {
+                    Some(Err(_)) => format!("{name} = {}...", value.text),
+                    _ => format!("{name} = {}", value.text),
+                }
+            })
+            .join(", ")
+    }
+}
+
+/// Writer that keeps the first `limit` characters and fails on more, so that formatting of
+/// huge values stops early
+struct Truncated {
+    text: String,
+    length: usize,
+    limit: usize,
+}
+
+impl std::fmt::Write for Truncated {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        for c in s.chars() {
+            if self.length == self.limit {
+                return Err(std::fmt::Error);
+            }
+            self.text.push(c);
+            self.length += 1;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Procedure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(
+                f,
+                "<procedure {name} with {} parameters>",
+                self.param_names.len()
+            ),
+            None => write!(f, "<procedure with {} parameters>", self.param_names.len()),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct BuiltIn {
+    pub name: &'static str,
     func: fn(Vec<Value>) -> Result<Value, RuntimeError>,
 }
 
 impl BuiltIn {
-    pub fn new(func: fn(Vec<Value>) -> Result<Value, RuntimeError>) -> Self {
-        Self { func }
+    pub fn new(name: &'static str, func: fn(Vec<Value>) -> Result<Value, RuntimeError>) -> Self {
+        Self { name, func }
     }
 
     pub fn call(&self, params: Vec<Value>) -> Result<Value, RuntimeError> {
@@ -53,48 +182,513 @@ impl BuiltIn {
     }
 }
 
+pub type SyntaxTransformer = fn(&[Rc<Expr>], &Rc<Scope>) -> Result<Value, RuntimeError>;
+
+/// Syntactic keyword bound in a scope like any other variable. Unlike procedures, special forms
+/// receive their operands unevaluated.
+#[derive(Debug, Clone)]
+pub struct SpecialForm {
+    name: &'static str,
+    func: SyntaxTransformer,
+}
+
+impl SpecialForm {
+    pub fn new(name: &'static str, func: SyntaxTransformer) -> Self {
+        Self { name, func }
+    }
+
+    pub fn call(&self, exprs: &[Rc<Expr>], scope: &Rc<Scope>) -> Result<Value, RuntimeError> {
+        (self.func)(exprs, scope)
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub field_names: Vec<String>,
+}
+
+impl RecordType {
+    /// Type name without the conventional angle brackets (`<point>` -> `point`)
+    pub fn display_name(&self) -> &str {
+        self.name
+            .strip_prefix('<')
+            .and_then(|name| name.strip_suffix('>'))
+            .unwrap_or(&self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub record_type: Rc<RecordType>,
+    pub fields: RefCell<Vec<Value>>,
+}
+
+/// Procedures generated by `define-record-type`
+#[derive(Debug)]
+pub enum RecordProcedure {
+    /// indices of fields initialized by constructor parameters
+    Constructor(Rc<RecordType>, Vec<usize>),
+    Predicate(Rc<RecordType>),
+    Accessor(Rc<RecordType>, usize),
+    Modifier(Rc<RecordType>, usize),
+}
+
+impl RecordProcedure {
+    fn record(record_type: &Rc<RecordType>, value: &Value) -> Result<Rc<Record>, RuntimeError> {
+        match value {
+            Value::Record(record) if Rc::ptr_eq(&record.record_type, record_type) => {
+                Ok(record.clone())
+            }
+            _ => Err(RuntimeError::RecordExpected(
+                record_type.name.clone(),
+                value.clone(),
+            )),
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        match self {
+            RecordProcedure::Constructor(_, indices) => Arity::Exactly(indices.len()),
+            RecordProcedure::Predicate(_) | RecordProcedure::Accessor(..) => Arity::Exactly(1),
+            RecordProcedure::Modifier(..) => Arity::Exactly(2),
+        }
+    }
+
+    pub fn call(&self, params: Vec<Value>) -> Result<Value, RuntimeError> {
+        let arity_error = || RuntimeError::WrongNumberOfAgumentsPassed {
+            expected: self.arity(),
+            passed: params.len(),
+        };
+        match (self, params.as_slice()) {
+            (RecordProcedure::Constructor(record_type, indices), _) => {
+                if indices.len() != params.len() {
+                    return Err(arity_error());
+                }
+                let mut fields = vec![Value::Unspecified; record_type.field_names.len()];
+                for (index, param) in indices.iter().zip(params) {
+                    fields[*index] = param;
+                }
+                Ok(Value::Record(Rc::new(Record {
+                    record_type: record_type.clone(),
+                    fields: RefCell::new(fields),
+                })))
+            }
+            (RecordProcedure::Predicate(record_type), [value]) => Ok(Value::Bool(
+                matches!(value, Value::Record(r) if Rc::ptr_eq(&r.record_type, record_type)),
+            )),
+            (RecordProcedure::Accessor(record_type, index), [value]) => {
+                let record = Self::record(record_type, value)?;
+                let field = record.fields.borrow()[*index].clone();
+                Ok(field)
+            }
+            (RecordProcedure::Modifier(record_type, index), [value, field]) => {
+                let record = Self::record(record_type, value)?;
+                record.fields.borrow_mut()[*index] = field.clone();
+                Ok(Value::Unspecified)
+            }
+            _ => Err(arity_error()),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Bool(bool),
     Int(i64),
     Float(f64),
+    Char(char),
     String(Rc<String>),
+    Symbol(Rc<String>),
     List(Vec<Value>),
+    Vector(Rc<RefCell<Vec<Value>>>),
+    HashTable(Rc<RefCell<HashMap<Value, Value>>>),
+    Set(Rc<RefCell<HashSet<Value>>>),
+    RecordType(Rc<RecordType>),
+    Record(Rc<Record>),
+    RecordProcedure(Rc<RecordProcedure>),
     BuiltIn(BuiltIn),
-    Procedure(Procedure),
-    Nil,
+    Procedure(Rc<Procedure>),
+    SpecialForm(SpecialForm),
+    /// first-class environment, see `the-environment`
+    Environment(Rc<Scope>),
+    /// result of expressions evaluated only for their side effects, e.g. `define` or `set!`
+    Unspecified,
+    Eof,
 }
 
 impl Value {
+    pub fn new_vector(items: Vec<Value>) -> Self {
+        Value::Vector(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn new_hash_table() -> Self {
+        Value::HashTable(Rc::new(RefCell::new(HashMap::new())))
+    }
+
+    #[allow(clippy::mutable_key_type)] // see `Hash for Value`
+    pub fn new_set(items: HashSet<Value>) -> Self {
+        Value::Set(Rc::new(RefCell::new(items)))
+    }
+
+    /// Identity comparison used by `eq?` and `eqv?`. Lists have no identity of their own
+    /// because they are immutable values, so they are compared element-wise.
+    pub fn eqv(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eqv(b))
+            }
+            (Value::Vector(a), Value::Vector(b)) => Rc::ptr_eq(a, b),
+            (Value::Record(a), Value::Record(b)) => Rc::ptr_eq(a, b),
+            _ => self == other,
+        }
+    }
+
+    /// External representation used by `write` and the REPL, see `Written`
+    pub fn written(&self, sharing: Sharing) -> Written<'_> {
+        Written {
+            value: self,
+            sharing,
+            display: false,
+        }
+    }
+
     pub fn truthy(&self) -> bool {
         match self {
-            Value::Nil => false,
             Value::Bool(b) => *b,
             _ => true,
         }
     }
 }
 
+/// Structural equality (`equal?`) also used for hash table keys. Floats are equal when their bit
+/// patterns are, so that every value is equal to itself. Procedures, hash tables and sets are
+/// equal only to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.equal(other, &mut HashSet::new())
+    }
+}
+
+impl Value {
+    /// `PartialEq` of values that may contain themselves. `compared` holds the pairs of vectors
+    /// and records whose comparison is in progress, they are taken as equal when they are
+    /// reached again, so that circular structure is compared in finite time.
+    fn equal(&self, other: &Value, compared: &mut HashSet<(*const (), *const ())>) -> bool {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Value::equal_items(a, b, compared),
+            (Value::Vector(a), Value::Vector(b)) => {
+                Rc::ptr_eq(a, b)
+                    || !compared.insert((Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ()))
+                    || Value::equal_items(&a.borrow(), &b.borrow(), compared)
+            }
+            (Value::HashTable(a), Value::HashTable(b)) => Rc::ptr_eq(a, b),
+            (Value::Set(a), Value::Set(b)) => Rc::ptr_eq(a, b),
+            (Value::RecordType(a), Value::RecordType(b)) => Rc::ptr_eq(a, b),
+            (Value::Record(a), Value::Record(b)) => {
+                Rc::ptr_eq(a, b)
+                    || !compared.insert((Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ()))
+                    || (Rc::ptr_eq(&a.record_type, &b.record_type)
+                        && Value::equal_items(&a.fields.borrow(), &b.fields.borrow(), compared))
+            }
+            (Value::RecordProcedure(a), Value::RecordProcedure(b)) => Rc::ptr_eq(a, b),
+            (Value::BuiltIn(a), Value::BuiltIn(b)) => std::ptr::fn_addr_eq(a.func, b.func),
+            (Value::SpecialForm(a), Value::SpecialForm(b)) => {
+                a.name == b.name && std::ptr::fn_addr_eq(a.func, b.func)
+            }
+            (Value::Procedure(a), Value::Procedure(b)) => {
+                Rc::ptr_eq(&a.scope, &b.scope)
+                    && a.param_names == b.param_names
+                    && a.body.len() == b.body.len()
+                    && a.body.iter().zip(&b.body).all(|(a, b)| Rc::ptr_eq(a, b))
+            }
+            (Value::Environment(a), Value::Environment(b)) => Rc::ptr_eq(a, b),
+            (Value::Unspecified, Value::Unspecified) | (Value::Eof, Value::Eof) => true,
+            _ => false,
+        }
+    }
+
+    fn equal_items(
+        a: &[Value],
+        b: &[Value],
+        compared: &mut HashSet<(*const (), *const ())>,
+    ) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equal(b, compared))
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Bool(b) => b.hash(state),
+            Value::Int(i) => i.hash(state),
+            Value::Float(f) => f.to_bits().hash(state),
+            Value::Char(c) => c.hash(state),
+            Value::String(s) | Value::Symbol(s) => s.hash(state),
+            Value::List(l) => l.hash(state),
+            // vectors can contain themselves, mutating a vector that is used as a key makes the
+            // entry unreachable
+            Value::Vector(v) => hash_shallow(&v.borrow(), state),
+            Value::HashTable(t) => Rc::as_ptr(t).hash(state),
+            Value::Set(s) => Rc::as_ptr(s).hash(state),
+            Value::RecordType(t) => Rc::as_ptr(t).hash(state),
+            Value::Record(r) => {
+                Rc::as_ptr(&r.record_type).hash(state);
+                hash_shallow(&r.fields.borrow(), state);
+            }
+            Value::RecordProcedure(p) => Rc::as_ptr(p).hash(state),
+            Value::SpecialForm(s) => s.name.hash(state),
+            Value::Environment(e) => Rc::as_ptr(e).hash(state),
+            Value::BuiltIn(_) | Value::Procedure(_) | Value::Unspecified | Value::Eof => {}
+        }
+    }
+}
+
+/// Hashes the length and the atoms of a mutable container without descending into other
+/// containers, so that circular structure is hashed in finite time. Equal containers have equal
+/// atoms, which keeps the hash consistent with `PartialEq`.
+fn hash_shallow<H: Hasher>(items: &[Value], state: &mut H) {
+    items.len().hash(state);
+    for item in items {
+        match item {
+            Value::Bool(_)
+            | Value::Int(_)
+            | Value::Float(_)
+            | Value::Char(_)
+            | Value::String(_)
+            | Value::Symbol(_) => item.hash(state),
+            _ => std::mem::discriminant(item).hash(state),
+        }
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Bool(v) => std::fmt::Display::fmt(v, f),
             Value::Int(v) => std::fmt::Display::fmt(v, f),
             Value::Float(v) => std::fmt::Display::fmt(v, f),
+            Value::Char(v) => std::fmt::Display::fmt(v, f),
             Value::String(v) => std::fmt::Display::fmt(v, f),
-            Value::List(l) => {
-                write!(f, "(")?;
-                for (i, v) in l.iter().enumerate() {
-                    write!(f, "{v}")?;
-                    if i != l.len() - 1 {
-                        write!(f, " ")?;
-                    }
+            Value::Symbol(v) => std::fmt::Display::fmt(v, f),
+            // compound values can contain themselves, `Written` labels the cycles
+            Value::List(_) | Value::Vector(_) | Value::Set(_) | Value::Record(_) => Written {
+                value: self,
+                sharing: Sharing::Cycles,
+                display: true,
+            }
+            .fmt(f),
+            Value::HashTable(t) => write!(f, "<hash table with {} entries>", t.borrow().len()),
+            Value::RecordType(t) => write!(f, "<record type {}>", t.display_name()),
+            Value::RecordProcedure(_) => write!(f, "<record procedure>"),
+            Value::BuiltIn(b) => write!(f, "<built-in function {}>", b.name),
+            Value::SpecialForm(s) => write!(f, "<special form {}>", s.name),
+            Value::Procedure(p) => std::fmt::Display::fmt(p, f),
+            Value::Environment(e) => {
+                write!(
+                    f,
+                    "<environment with {} bindings>",
+                    Scope::bound_names(e).len()
+                )
+            }
+            Value::Unspecified => write!(f, "#<unspecified>"),
+            Value::Eof => write!(f, "#<eof>"),
+        }
+    }
+}
+
+/// How `Written` marks vectors and records that are reached more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sharing {
+    /// `write-simple`, no datum labels, never finishes on circular data
+    Never,
+    /// `write`, labels only the structure that contains itself
+    Cycles,
+    /// `write-shared`, labels all structure that is reached more than once
+    Always,
+}
+
+/// Value written so that the reader turns it back into an equal datum, unlike `Display` which is
+/// meant for `display`: strings are quoted and escaped, floats keep their decimal point and
+/// characters and booleans are written as literals. Shared structure gets datum labels, e.g.
+/// `#0=#(1 #0#)` is a vector that contains itself.
+pub struct Written<'a> {
+    value: &'a Value,
+    sharing: Sharing,
+    /// Writes atoms as `Display` does, used to display compound values
+    display: bool,
+}
+
+/// Address of a value that has an identity, only those can be shared
+fn address(value: &Value) -> Option<*const ()> {
+    match value {
+        Value::Vector(v) => Some(Rc::as_ptr(v) as *const ()),
+        Value::Set(s) => Some(Rc::as_ptr(s) as *const ()),
+        Value::Record(r) => Some(Rc::as_ptr(r) as *const ()),
+        _ => None,
+    }
+}
+
+impl Written<'_> {
+    /// Collects the addresses that get a datum label into `labelled`, `path` holds the
+    /// structure that contains the current value
+    fn find_shared(
+        &self,
+        value: &Value,
+        visited: &mut HashSet<*const ()>,
+        path: &mut HashSet<*const ()>,
+        labelled: &mut HashSet<*const ()>,
+    ) {
+        let address = address(value);
+        if let Some(address) = address {
+            if !visited.insert(address) {
+                if self.sharing == Sharing::Always || path.contains(&address) {
+                    labelled.insert(address);
+                }
+                return;
+            }
+            path.insert(address);
+        }
+        match value {
+            Value::List(items) => {
+                for item in items {
+                    self.find_shared(item, visited, path, labelled);
+                }
+            }
+            Value::Vector(items) => {
+                for item in items.borrow().iter() {
+                    self.find_shared(item, visited, path, labelled);
+                }
+            }
+            Value::Set(items) => {
+                for item in items.borrow().iter() {
+                    self.find_shared(item, visited, path, labelled);
+                }
+            }
+            Value::Record(r) => {
+                for field in r.fields.borrow().iter() {
+                    self.find_shared(field, visited, path, labelled);
                 }
+            }
+            _ => {}
+        }
+        if let Some(address) = address {
+            path.remove(&address);
+        }
+    }
+
+    /// Writes the value, `labels` maps the labelled addresses written so far to their numbers
+    fn write(
+        &self,
+        value: &Value,
+        f: &mut std::fmt::Formatter<'_>,
+        labelled: &HashSet<*const ()>,
+        labels: &mut HashMap<*const (), usize>,
+    ) -> std::fmt::Result {
+        if let Some(address) = address(value).filter(|a| labelled.contains(a)) {
+            if let Some(label) = labels.get(&address) {
+                return write!(f, "#{label}#");
+            }
+            let label = labels.len();
+            labels.insert(address, label);
+            write!(f, "#{label}=")?;
+        }
+        match value {
+            Value::List(items) => {
+                write!(f, "(")?;
+                self.write_items(items, f, labelled, labels)?;
+                write!(f, ")")
+            }
+            Value::Vector(items) => {
+                write!(f, "#(")?;
+                self.write_items(&items.borrow(), f, labelled, labels)?;
                 write!(f, ")")
             }
-            Value::BuiltIn(_) => write!(f, "<built-in function>"),
-            Value::Procedure(p) => write!(f, "<procedure with {} parameters>", p.param_names.len()),
-            Value::Nil => write!(f, "#nil"),
+            // sets have no order of their own, sorting keeps the output stable
+            Value::Set(s) => {
+                let items = s
+                    .borrow()
+                    .iter()
+                    .sorted_by_cached_key(|item| self.nested(item).to_string())
+                    .cloned()
+                    .collect_vec();
+                write!(f, "#<set")?;
+                for item in &items {
+                    write!(f, " ")?;
+                    self.write(item, f, labelled, labels)?;
+                }
+                write!(f, ">")
+            }
+            Value::Record(r) => {
+                write!(f, "#<{}", r.record_type.display_name())?;
+                for (name, value) in r
+                    .record_type
+                    .field_names
+                    .iter()
+                    .zip(r.fields.borrow().iter())
+                {
+                    write!(f, " {name}: ")?;
+                    self.write(value, f, labelled, labels)?;
+                }
+                write!(f, ">")
+            }
+            value if self.display => std::fmt::Display::fmt(value, f),
+            Value::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }),
+            Value::Float(v) => write!(f, "{}", float_to_string(*v)),
+            Value::Char(c) => write!(f, "{}", char_to_string(*c)),
+            Value::String(s) => write!(f, "{}", escape_string(s)),
+            Value::Symbol(s) => write!(f, "{}", symbol_to_string(s)),
+            value => std::fmt::Display::fmt(value, f),
+        }
+    }
+
+    /// Element written on its own in the same representation
+    fn nested<'v>(&self, value: &'v Value) -> Written<'v> {
+        Written {
+            value,
+            sharing: self.sharing,
+            display: self.display,
+        }
+    }
+
+    fn write_items(
+        &self,
+        items: &[Value],
+        f: &mut std::fmt::Formatter<'_>,
+        labelled: &HashSet<*const ()>,
+        labels: &mut HashMap<*const (), usize>,
+    ) -> std::fmt::Result {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            self.write(item, f, labelled, labels)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Written<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut labelled = HashSet::new();
+        if self.sharing != Sharing::Never {
+            self.find_shared(
+                self.value,
+                &mut HashSet::new(),
+                &mut HashSet::new(),
+                &mut labelled,
+            );
         }
+        self.write(self.value, f, &labelled, &mut HashMap::new())
     }
 }